@@ -0,0 +1,257 @@
+use std::fmt;
+use std::fs::Metadata;
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+use crate::scanner::SkipReason;
+
+/// Which time of a file the age is measured by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timestamp {
+    /// Last modification (mtime).
+    #[default]
+    Modified,
+    /// Last access (atime).
+    Accessed,
+    /// Last status change (ctime). Systems without a ctime use the creation
+    /// time instead.
+    Changed,
+}
+
+/// A point in time, either a date like `2024-03-01` (or `2024-03-01T12:00`)
+/// or an age relative to now like `90d`.
+///
+/// Relative ages take a unit of `s` (seconds), `m` (minutes), `h`, `d`, `w`
+/// or `y` (365 days).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSpec {
+    Date(NaiveDateTime),
+    Ago(Duration),
+}
+
+/// Selects files by the age of one of their timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AgeFilter {
+    timestamp: Timestamp,
+    older_than: Option<TimeSpec>,
+    newer_than: Option<TimeSpec>,
+}
+
+impl AgeFilter {
+    pub fn new() -> Self {
+        AgeFilter::default()
+    }
+
+    /// Sets the timestamp the age is measured by (mtime by default).
+    pub fn timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Only selects files older than `time`.
+    pub fn older_than(mut self, time: TimeSpec) -> Self {
+        self.older_than = Some(time);
+        self
+    }
+
+    /// Only selects files newer than `time`.
+    pub fn newer_than(mut self, time: TimeSpec) -> Self {
+        self.newer_than = Some(time);
+        self
+    }
+
+    /// Why a file is left out, or `None` if its age is within the limits.
+    /// Files whose timestamp can't be read are never selected by age.
+    pub(crate) fn check(&self, metadata: &Metadata, now: DateTime<Local>) -> Option<SkipReason> {
+        let time = match self.timestamp.of(metadata) {
+            Ok(time) => time,
+            Err(_) => return Some(SkipReason::Unreadable),
+        };
+        if self.older_than.is_some_and(|limit| time >= limit.resolve(now)) {
+            return Some(SkipReason::TooNew);
+        }
+        if self.newer_than.is_some_and(|limit| time <= limit.resolve(now)) {
+            return Some(SkipReason::TooOld);
+        }
+        None
+    }
+}
+
+impl Timestamp {
+    fn of(&self, metadata: &Metadata) -> io::Result<SystemTime> {
+        match self {
+            Timestamp::Modified => metadata.modified(),
+            Timestamp::Accessed => metadata.accessed(),
+            #[cfg(unix)]
+            Timestamp::Changed => {
+                use std::os::unix::fs::MetadataExt;
+                let changed = Duration::new(metadata.ctime().max(0) as u64, metadata.ctime_nsec().max(0) as u32);
+                Ok(SystemTime::UNIX_EPOCH + changed)
+            }
+            #[cfg(not(unix))]
+            Timestamp::Changed => metadata.created(),
+        }
+    }
+}
+
+impl TimeSpec {
+    /// The point in time, with relative ages counted back from `now`.
+    pub fn resolve(&self, now: DateTime<Local>) -> SystemTime {
+        match self {
+            TimeSpec::Date(date) => Local
+                .from_local_datetime(date)
+                .earliest()
+                .map(SystemTime::from)
+                .unwrap_or_else(|| SystemTime::from(date.and_utc())),
+            TimeSpec::Ago(age) => SystemTime::from(now).checked_sub(*age).unwrap_or(SystemTime::UNIX_EPOCH),
+        }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timestamp::Modified => write!(f, "mtime"),
+            Timestamp::Accessed => write!(f, "atime"),
+            Timestamp::Changed => write!(f, "ctime"),
+        }
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "mtime" | "modified" => Ok(Timestamp::Modified),
+            "atime" | "accessed" => Ok(Timestamp::Accessed),
+            "ctime" | "changed" => Ok(Timestamp::Changed),
+            _ => Err(format!("unknown timestamp '{}' (expected mtime, atime or ctime)", input)),
+        }
+    }
+}
+
+impl fmt::Display for TimeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeSpec::Date(date) => write!(f, "{}", date.format("%Y-%m-%d %H:%M")),
+            TimeSpec::Ago(age) => {
+                let seconds = age.as_secs();
+                match seconds {
+                    _ if seconds % (60 * 60 * 24) == 0 => write!(f, "{}d", seconds / (60 * 60 * 24)),
+                    _ if seconds % (60 * 60) == 0 => write!(f, "{}h", seconds / (60 * 60)),
+                    _ if seconds % 60 == 0 => write!(f, "{}m", seconds / 60),
+                    _ => write!(f, "{}s", seconds),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for TimeSpec {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+            return Ok(TimeSpec::Date(date.and_hms_opt(0, 0, 0).unwrap_or_default()));
+        }
+        for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+            if let Ok(date) = NaiveDateTime::parse_from_str(input, format) {
+                return Ok(TimeSpec::Date(date));
+            }
+        }
+
+        let split = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+        let (number, unit) = input.split_at(split);
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid time '{}' (expected a date like 2024-03-01 or an age like 90d)", input))?;
+        let seconds = match unit.trim() {
+            "s" => 1,
+            "m" | "min" => 60,
+            "h" => 60 * 60,
+            "d" => 60 * 60 * 24,
+            "w" => 60 * 60 * 24 * 7,
+            "y" => 60 * 60 * 24 * 365,
+            _ => return Err(format!("unknown time unit '{}' (expected s, m, h, d, w or y)", unit)),
+        };
+        let age = number.checked_mul(seconds).ok_or_else(|| format!("the time '{}' is too long", input))?;
+        Ok(TimeSpec::Ago(Duration::from_secs(age)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> TimeSpec {
+        let date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        TimeSpec::Date(date.and_hms_opt(hour, minute, 0).unwrap())
+    }
+
+    #[test]
+    fn parse_dates() {
+        assert_eq!("2024-03-01".parse(), Ok(date(2024, 3, 1, 0, 0)));
+        assert_eq!(" 2024-03-01T12:30 ".parse(), Ok(date(2024, 3, 1, 12, 30)));
+        assert_eq!("2024-03-01 12:30".parse(), Ok(date(2024, 3, 1, 12, 30)));
+        assert_eq!("2024-03-01T12:30:00".parse(), Ok(date(2024, 3, 1, 12, 30)));
+        assert!("2024-02-30".parse::<TimeSpec>().is_err());
+    }
+
+    #[test]
+    fn parse_ages() {
+        let ago = |seconds| Ok(TimeSpec::Ago(Duration::from_secs(seconds)));
+        assert_eq!("45s".parse(), ago(45));
+        assert_eq!("10m".parse(), ago(600));
+        assert_eq!("10min".parse(), ago(600));
+        assert_eq!("36h".parse(), ago(36 * 60 * 60));
+        assert_eq!("90d".parse(), ago(90 * 24 * 60 * 60));
+        assert_eq!("2w".parse(), ago(14 * 24 * 60 * 60));
+        assert_eq!("1y".parse(), ago(365 * 24 * 60 * 60));
+        assert_eq!("0d".parse(), ago(0));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for input in ["", "d", "90", "90x", "-5d", "1.5d", "99999999999999999y"] {
+            assert!(input.parse::<TimeSpec>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn display() {
+        for input in ["90d", "36h", "10m", "45s", "2024-03-01 12:30"] {
+            assert_eq!(input.parse::<TimeSpec>().unwrap().to_string(), input);
+        }
+        assert_eq!("2w".parse::<TimeSpec>().unwrap().to_string(), "14d");
+        assert_eq!("120m".parse::<TimeSpec>().unwrap().to_string(), "2h");
+    }
+
+    #[test]
+    fn resolve() {
+        let now = Local::now();
+        let ago = TimeSpec::Ago(Duration::from_secs(60));
+        assert_eq!(ago.resolve(now), SystemTime::from(now) - Duration::from_secs(60));
+
+        let forever = TimeSpec::Ago(Duration::from_secs(u64::MAX));
+        assert_eq!(forever.resolve(now), SystemTime::UNIX_EPOCH);
+
+        let noon = date(2024, 3, 1, 12, 0);
+        let TimeSpec::Date(naive) = noon else { unreachable!() };
+        let expected = SystemTime::from(Local.from_local_datetime(&naive).earliest().unwrap());
+        assert_eq!(noon.resolve(now), expected);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!("mtime".parse(), Ok(Timestamp::Modified));
+        assert_eq!("Accessed".parse(), Ok(Timestamp::Accessed));
+        assert_eq!(" ctime ".parse(), Ok(Timestamp::Changed));
+        assert!("birth".parse::<Timestamp>().is_err());
+        assert_eq!(Timestamp::Changed.to_string(), "ctime");
+    }
+}
@@ -0,0 +1,548 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use chrono::{DateTime, Local};
+use zip::write::{FileOptions, SimpleFileOptions};
+use zip::{AesMode, ZipWriter};
+
+use crate::catalog::Catalog;
+use crate::compression::Compression;
+use crate::error::{Error, Result};
+use crate::format::{self, Format, TarEncoder};
+use crate::manifest::{HashingReader, Manifest, MANIFEST_NAME};
+use crate::password::Password;
+use crate::plan::{ArchivePlan, PlannedArchive};
+use crate::policy::{self, CompressionPolicy};
+use crate::recipient::{self, Recipient};
+use crate::report::{ArchiveReport, ArchiveResult};
+use crate::scanner::FileEntry;
+use crate::volume::{self, VolumeWriter};
+
+/// Writes the archives of an [`ArchivePlan`].
+#[derive(Debug, Clone, Default)]
+pub struct Archiver {
+    compression: Compression,
+    policy: CompressionPolicy,
+    format: Format,
+    catalog: bool,
+    volume_size: Option<u64>,
+    password: Option<Password>,
+    recipients: Vec<Recipient>,
+    jobs: usize,
+}
+
+impl Archiver {
+    pub fn new() -> Self {
+        Archiver::default()
+    }
+
+    /// Sets the compression of the archive entries (deflate by default).
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the per file type compression policy. By default already
+    /// compressed formats are stored.
+    pub fn policy(mut self, policy: CompressionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the format of the archives (zip by default).
+    ///
+    /// Tarballs are compressed as a whole with the format's method, using
+    /// the level of [`compression`](Archiver::compression) if it is the same
+    /// method. The compression policy only applies to zip archives.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Records every written archive in the catalog of the archive folder
+    /// (off by default), see [`Catalog`].
+    pub fn catalog(mut self, catalog: bool) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    /// Splits archives larger than `volume_size` bytes into volumes (off by
+    /// default), see [`volume`](crate::volume).
+    pub fn volume_size(mut self, volume_size: Option<u64>) -> Self {
+        self.volume_size = volume_size;
+        self
+    }
+
+    /// Encrypts the entries of zip archives with AES-256 (off by default),
+    /// readable by WinZip, 7-Zip and other tools supporting WinZip AES.
+    /// Entry names stay visible; the manifest is encrypted as well. Tarballs
+    /// can't be encrypted.
+    pub fn password(mut self, password: Option<Password>) -> Self {
+        self.password = password;
+        self
+    }
+
+    /// Encrypts every written archive to `recipients` (none by default), see
+    /// [`recipient`](crate::recipient). The archives get the ending `.age`
+    /// and can only be read with the identity of one of the recipients, so
+    /// they are verified before they are encrypted.
+    pub fn recipients(mut self, recipients: Vec<Recipient>) -> Self {
+        self.recipients = recipients;
+        self
+    }
+
+    /// Writes the archives on up to `jobs` threads at once. `0` (the
+    /// default) uses one thread per CPU core, `1` writes them one after
+    /// another. The archives and the report are the same either way.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Creates the archive folder and writes every archive of the plan.
+    ///
+    /// Files that can't be read are skipped and listed in the report.
+    pub fn execute(&self, plan: &ArchivePlan) -> Result<ArchiveReport> {
+        self.execute_with_progress(plan, |_| {})
+    }
+
+    /// Like [`execute`](Archiver::execute), calling `progress` with every
+    /// archive as soon as it is written or has failed. Archives written at
+    /// the same time finish in any order; the report lists them in the order
+    /// of the plan.
+    ///
+    /// An archive that fails doesn't stop the others, its error is kept in
+    /// the report. An archive that can't be recorded in the catalog counts
+    /// as failed, so its files are never removed.
+    pub fn execute_with_progress(&self, plan: &ArchivePlan, mut progress: impl FnMut(&Result<ArchiveResult>)) -> Result<ArchiveReport> {
+        if self.password.is_some() && self.format.is_tar() {
+            return Err(Error::EncryptionUnsupported(self.format));
+        }
+        fs::create_dir_all(&plan.archive_folder).map_err(|err| Error::io(&plan.archive_folder, err))?;
+
+        let mut catalog = if self.catalog {
+            Some(Catalog::open(&plan.archive_folder)?)
+        } else {
+            None
+        };
+
+        //the workers take the next archive of the plan until none are left
+        let mut results: Vec<Option<Result<ArchiveResult>>> = plan.archives.iter().map(|_| None).collect();
+        let next = &AtomicUsize::new(0);
+        thread::scope(|scope| {
+            let (sender, finished) = mpsc::channel();
+            for _ in 0..self.worker_count(plan.archives.len()) {
+                let sender = sender.clone();
+                scope.spawn(move || loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= plan.archives.len() {
+                        break;
+                    }
+                    let result = self.write_archive(plan, &plan.archives[index]);
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            for (index, result) in finished {
+                let result = match (result, &mut catalog) {
+                    (Ok(result), Some(catalog)) => catalog.record(&result).map(|()| result),
+                    (result, _) => result,
+                };
+                progress(&result);
+                results[index] = Some(result);
+            }
+        });
+
+        Ok(ArchiveReport {
+            archives: results.into_iter().flatten().collect(),
+        })
+    }
+
+    /// Number of threads writing `archives` archives.
+    fn worker_count(&self, archives: usize) -> usize {
+        let jobs = match self.jobs {
+            0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            jobs => jobs,
+        };
+        jobs.min(archives).max(1)
+    }
+
+    /// Writes a single archive of the plan straight into its volumes, then
+    /// encrypts it.
+    fn write_archive(&self, plan: &ArchivePlan, archive: &PlannedArchive) -> Result<ArchiveResult> {
+        let mut result = ArchiveResult {
+            path: plan.archive_folder.join(archive.file_name(self.format)),
+            format: self.format,
+            files: Vec::new(),
+            errors: Vec::new(),
+            compression: self.format.compression(self.compression),
+            stored_files: 0,
+            original_size: 0,
+            archive_size: 0,
+            volumes: Vec::new(),
+            password: self.password.clone(),
+            recipients: self.recipients.clone(),
+            verification: None,
+            manifest: Manifest::new(plan, archive),
+        };
+
+        if self.format.is_tar() {
+            self.write_tar(plan, archive, &mut result)?;
+        } else {
+            self.write_zip(archive, &mut result)?;
+        }
+
+        if !self.recipients.is_empty() {
+            self.encrypt(&mut result)?;
+        }
+
+        let path = result.path.clone();
+        result.archive_size = volume::total_size(&path).map_err(|err| Error::io(&path, err))?;
+        Ok(result)
+    }
+
+    /// Completes the archive written by `writer` and lists its volumes in the
+    /// result if it is split.
+    fn finish_volumes(writer: VolumeWriter, result: &mut ArchiveResult) -> Result<()> {
+        let volumes = writer.finish().map_err(|err| Error::io(&result.path, err))?;
+        result.volumes = if volumes.len() > 1 { volumes } else { Vec::new() };
+        Ok(())
+    }
+
+    /// Writes a zip archive, with the manifest as the last entry.
+    fn write_zip(&self, archive: &PlannedArchive, result: &mut ArchiveResult) -> Result<()> {
+        let zip_path = result.path.clone();
+        let writer = VolumeWriter::create(&zip_path, self.volume_size).map_err(|err| Error::io(&zip_path, err))?;
+        let mut zip = ZipWriter::new(writer);
+        let options = compression_options(self.compression);
+
+        for directory in &archive.directories {
+            zip.add_directory(directory.as_str(), options).map_err(|err| Error::zip(&zip_path, err))?;
+        }
+
+        for entry in &archive.files {
+            let mut file = match fs::File::open(&entry.path) {
+                Ok(file) => file,
+                Err(err) => {
+                    result.errors.push((entry.path.clone(), format!("could not open file: {}", err)));
+                    continue;
+                }
+            };
+
+            let (compression, sample) = match self.choose_compression(entry, &mut file) {
+                Ok(choice) => choice,
+                Err(err) => {
+                    result.errors.push((entry.path.clone(), format!("could not read file: {}", err)));
+                    continue;
+                }
+            };
+            if compression == Compression::Stored && self.compression != Compression::Stored {
+                result.stored_files += 1;
+            }
+
+            let metadata = file.metadata().ok();
+            let mut options = compression_options(compression).large_file(entry.size >= u32::MAX as u64);
+            if let Some(metadata) = &metadata {
+                options = with_metadata(options, metadata);
+            }
+            if let Err(err) = zip.start_file(entry.name.as_str(), encrypted(options, self.password.as_ref())) {
+                result.errors.push((entry.path.clone(), format!("could not start file in zip: {}", err)));
+                continue;
+            }
+
+            let mut reader = HashingReader::new(sample.as_slice().chain(file));
+            if let Err(err) = io::copy(&mut reader, &mut zip) {
+                result.errors.push((entry.path.clone(), format!("could not copy file to zip: {}", err)));
+                //don't leave a truncated entry behind
+                zip.abort_file().map_err(|err| Error::zip(&zip_path, err))?;
+                continue;
+            }
+
+            result.manifest.add(entry, metadata.as_ref(), reader.sha256());
+            result.files.push(entry.clone());
+            result.original_size += entry.size;
+        }
+
+        //the manifest is dated by the plan, so the same plan gives the same archive
+        let mut manifest_options = options;
+        if let Ok(created) = zip::DateTime::try_from(archive.created.naive_local()) {
+            manifest_options = manifest_options.last_modified_time(created);
+        }
+        zip.start_file(MANIFEST_NAME, encrypted(manifest_options, self.password.as_ref()))
+            .map_err(|err| Error::zip(&zip_path, err))?;
+        zip.write_all(&result.manifest.to_json()).map_err(|err| Error::io(&zip_path, err))?;
+        let writer = zip.finish().map_err(|err| Error::zip(&zip_path, err))?;
+        Self::finish_volumes(writer, result)
+    }
+
+    /// Writes a compressed tarball, with the manifest as the last entry. The
+    /// headers keep the unix permissions, modification times and owners of
+    /// the files.
+    fn write_tar(&self, plan: &ArchivePlan, archive: &PlannedArchive, result: &mut ArchiveResult) -> Result<()> {
+        let tar_path = result.path.clone();
+        let writer = VolumeWriter::create(&tar_path, self.volume_size).map_err(|err| Error::io(&tar_path, err))?;
+        let encoder = TarEncoder::new(self.format, result.compression, writer).map_err(|err| Error::io(&tar_path, err))?;
+        let mut tar = tar::Builder::new(encoder);
+
+        for directory in &archive.directories {
+            tar.append_dir(directory, plan.folder.join(directory))
+                .map_err(|err| Error::io(&tar_path, err))?;
+        }
+
+        for entry in &archive.files {
+            let opened = fs::File::open(&entry.path).and_then(|file| file.metadata().map(|metadata| (file, metadata)));
+            let (file, metadata) = match opened {
+                Ok(opened) => opened,
+                Err(err) => {
+                    result.errors.push((entry.path.clone(), format!("could not open file: {}", err)));
+                    continue;
+                }
+            };
+
+            //the header is written first, so the entry gets exactly the size in
+            //it whatever happens to the file while it is read
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&metadata);
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(metadata.len());
+            let mut reader = SizedReader::new(HashingReader::new(file), metadata.len());
+            tar.append_data(&mut header, &entry.name, &mut reader)
+                .map_err(|err| Error::io(&tar_path, err))?;
+            if let Some(err) = reader.error {
+                //the padded entry stays out of the manifest, so it is never
+                //restored or removed
+                result.errors.push((entry.path.clone(), format!("could not read file: {}", err)));
+                continue;
+            }
+
+            result.manifest.add(entry, Some(&metadata), reader.inner.sha256());
+            result.files.push(entry.clone());
+            result.original_size += entry.size;
+        }
+
+        let contents = result.manifest.to_json();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(archive.created.timestamp().max(0) as u64);
+        tar.append_data(&mut header, MANIFEST_NAME, contents.as_slice())
+            .map_err(|err| Error::io(&tar_path, err))?;
+
+        let encoder = tar.into_inner().map_err(|err| Error::io(&tar_path, err))?;
+        let writer = encoder.finish().map_err(|err| Error::io(&tar_path, err))?;
+        Self::finish_volumes(writer, result)
+    }
+
+    /// Verifies the written archive, which can't be read anymore afterwards,
+    /// and replaces it with the archive encrypted to the recipients, split
+    /// into volumes of its own.
+    fn encrypt(&self, result: &mut ArchiveResult) -> Result<()> {
+        result.verify();
+
+        let path = result.path.clone();
+        let mut encrypted_name = path.file_name().unwrap_or_default().to_os_string();
+        encrypted_name.push(format::ENCRYPTED_SUFFIX);
+        let encrypted_path = path.with_file_name(encrypted_name);
+
+        let input = volume::open(&path).map_err(|err| Error::io(&path, err))?;
+        let mut output = VolumeWriter::create(&encrypted_path, self.volume_size).map_err(|err| Error::io(&encrypted_path, err))?;
+        recipient::encrypt(io::BufReader::new(input), io::BufWriter::new(&mut output), &self.recipients)
+            .map_err(|err| Error::io(&encrypted_path, err))?;
+
+        let plain = if result.volumes.is_empty() { vec![path] } else { std::mem::take(&mut result.volumes) };
+        result.path = encrypted_path;
+        Self::finish_volumes(output, result)?;
+        for volume in plain {
+            fs::remove_file(&volume).map_err(|err| Error::io(&volume, err))?;
+        }
+        Ok(())
+    }
+
+    /// Picks the compression of a file from the policy. When sampling, the
+    /// first block of the file is read and returned, so it has to be written
+    /// before the rest of the file.
+    fn choose_compression(&self, entry: &FileEntry, file: &mut fs::File) -> io::Result<(Compression, Vec<u8>)> {
+        if let Some(compression) = self.policy.lookup(entry) {
+            return Ok((compression, Vec::new()));
+        }
+        if !self.policy.is_sampling() || self.compression == Compression::Stored {
+            return Ok((self.compression, Vec::new()));
+        }
+
+        let sample = policy::read_sample(file)?;
+        if policy::compresses_well(&sample) {
+            Ok((self.compression, sample))
+        } else {
+            Ok((Compression::Stored, sample))
+        }
+    }
+}
+
+/// Reads exactly `remaining` bytes, the size already written in a tar
+/// header: a file that grew is cut off, and one that shrank or can't be read
+/// anymore is padded with zeros, as GNU tar does, keeping the error.
+struct SizedReader<R> {
+    inner: R,
+    remaining: u64,
+    error: Option<io::Error>,
+}
+
+impl<R: Read> SizedReader<R> {
+    fn new(inner: R, size: u64) -> Self {
+        SizedReader {
+            inner,
+            remaining: size,
+            error: None,
+        }
+    }
+}
+
+impl<R: Read> Read for SizedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        if len == 0 {
+            return Ok(0);
+        }
+        let buf = &mut buf[..len];
+
+        let read = if self.error.is_some() {
+            0
+        } else {
+            match self.inner.read(buf) {
+                Ok(0) => {
+                    self.error = Some(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank while it was archived"));
+                    0
+                }
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => return Err(err),
+                Err(err) => {
+                    self.error = Some(err);
+                    0
+                }
+            }
+        };
+        let read = if read == 0 {
+            buf.fill(0);
+            len
+        } else {
+            read
+        };
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+fn compression_options(compression: Compression) -> SimpleFileOptions {
+    SimpleFileOptions::default()
+        .compression_method(compression.method())
+        .compression_level(compression.level())
+}
+
+/// Encrypts an entry with `password`, if there is one.
+fn encrypted(options: SimpleFileOptions, password: Option<&Password>) -> FileOptions<'_, ()> {
+    match password {
+        Some(password) => options.with_aes_encryption(AesMode::Aes256, password.as_str()),
+        None => options,
+    }
+}
+
+/// Stores the modification time and, on unix, the permissions of a file in
+/// its zip entry, so they can be restored.
+fn with_metadata(mut options: SimpleFileOptions, metadata: &fs::Metadata) -> SimpleFileOptions {
+    if let Ok(modified) = metadata.modified() {
+        let modified = DateTime::<Local>::from(modified).naive_local();
+        if let Ok(modified) = zip::DateTime::try_from(modified) {
+            options = options.last_modified_time(modified);
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        options = options.unix_permissions(metadata.permissions().mode() & 0o7777);
+    }
+
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails after the given contents, like a file on a failing drive.
+    struct Failing(&'static [u8]);
+
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::other("read error"));
+            }
+            let read = self.0.read(buf)?;
+            Ok(read)
+        }
+    }
+
+    fn read_sized(inner: impl Read, size: u64) -> (Vec<u8>, Option<io::Error>) {
+        let mut reader = SizedReader::new(inner, size);
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        (output, reader.error)
+    }
+
+    #[test]
+    fn sized_reader() {
+        let (output, error) = read_sized(&b"contents"[..], 8);
+        assert_eq!(output, b"contents");
+        assert!(error.is_none());
+
+        //a file that grew is cut off at the size in the header
+        let (output, error) = read_sized(&b"contents and more"[..], 8);
+        assert_eq!(output, b"contents");
+        assert!(error.is_none());
+
+        let (output, error) = read_sized(&b"short"[..], 8);
+        assert_eq!(output, b"short\0\0\0");
+        assert_eq!(error.unwrap().kind(), io::ErrorKind::UnexpectedEof);
+
+        let (output, error) = read_sized(Failing(b"abc"), 6);
+        assert_eq!(output, b"abc\0\0\0");
+        assert_eq!(error.unwrap().to_string(), "read error");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_files_are_skipped_in_tarballs() {
+        use crate::plan::ArchivePlan;
+        use crate::scanner::Scanner;
+
+        let folder = tempfile::tempdir().unwrap();
+        fs::write(folder.path().join("a.txt"), "a").unwrap();
+        fs::write(folder.path().join("b.txt"), "b").unwrap();
+        let scan = Scanner::new(folder.path()).scan().unwrap();
+        //a folder where a file was scanned fails when it is read
+        fs::remove_file(folder.path().join("b.txt")).unwrap();
+        fs::create_dir(folder.path().join("b.txt")).unwrap();
+
+        let plan = ArchivePlan::all(&scan);
+        let report = Archiver::new().format(Format::TarGz).catalog(false).execute(&plan).unwrap();
+        let archive = report.written().next().unwrap();
+        assert_eq!(archive.files.len(), 1);
+        assert_eq!(archive.files[0].name, "a.txt");
+        assert_eq!(archive.errors.len(), 1);
+        assert_eq!(archive.manifest.files.len(), 1);
+
+        //the padded entry is still in the tarball, but never restored
+        let target = folder.path().join("restored");
+        let restored = crate::restore::Restorer::new().target(&target).restore(&archive.path).unwrap();
+        assert_eq!(restored.entries.len(), 2);
+        assert!(target.join("a.txt").is_file());
+        assert!(!target.join("b.txt").exists());
+    }
+}
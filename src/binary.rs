@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, Metadata};
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// File endings of binaries, detected without looking at the contents.
+const BINARY_ENDINGS: &[&str] = &["exe"];
+
+/// Kinds of executables the scanner recognizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum BinaryClass {
+    /// Linux and BSD executables and libraries.
+    Elf,
+    /// Windows executables and DLLs (`MZ` header or `.exe` ending).
+    Pe,
+    /// macOS executables and libraries.
+    MachO,
+    /// Scripts starting with a `#!` line.
+    Script,
+    /// Other files with the unix exec bit set.
+    Executable,
+}
+
+/// What happens to the files of a [`BinaryClass`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryAction {
+    /// Leave the files out.
+    Exclude,
+    /// Archive the files like any other file.
+    Include,
+    /// Archive the files into a separate archive per class.
+    OwnArchive,
+}
+
+/// The [`BinaryAction`] of every binary class. Compiled executables (ELF,
+/// PE and Mach-O) are excluded by default; scripts and other files with the
+/// exec bit are archived like any other file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinaryPolicy {
+    actions: HashMap<BinaryClass, BinaryAction>,
+}
+
+impl BinaryPolicy {
+    pub fn new() -> Self {
+        BinaryPolicy::default()
+    }
+
+    /// Sets what happens to the files of `class`.
+    pub fn set(mut self, class: BinaryClass, action: BinaryAction) -> Self {
+        self.actions.insert(class, action);
+        self
+    }
+
+    pub fn action(&self, class: BinaryClass) -> BinaryAction {
+        self.actions.get(&class).copied().unwrap_or_else(|| class.default_action())
+    }
+}
+
+impl BinaryClass {
+    /// What happens to the files of the class if the policy doesn't say.
+    pub fn default_action(&self) -> BinaryAction {
+        match self {
+            BinaryClass::Elf | BinaryClass::Pe | BinaryClass::MachO => BinaryAction::Exclude,
+            BinaryClass::Script | BinaryClass::Executable => BinaryAction::Include,
+        }
+    }
+
+    /// Name used on the command line and for the archives of the class.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BinaryClass::Elf => "elf",
+            BinaryClass::Pe => "pe",
+            BinaryClass::MachO => "macho",
+            BinaryClass::Script => "script",
+            BinaryClass::Executable => "executable",
+        }
+    }
+}
+
+/// Detects executables by their ending, their first bytes and the unix exec
+/// bit. Returns `None` for other files.
+pub fn detect(path: &Path, metadata: &Metadata) -> io::Result<Option<BinaryClass>> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+    if extension.as_deref().is_some_and(|ext| BINARY_ENDINGS.contains(&ext)) {
+        return Ok(Some(BinaryClass::Pe));
+    }
+
+    let mut header = Vec::with_capacity(8);
+    File::open(path)?.take(8).read_to_end(&mut header)?;
+    if let Some(class) = detect_magic(&header) {
+        return Ok(Some(class));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 != 0 {
+            return Ok(Some(BinaryClass::Executable));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+
+    Ok(None)
+}
+
+fn detect_magic(header: &[u8]) -> Option<BinaryClass> {
+    const MACHO_MAGIC: [[u8; 4]; 4] = [
+        [0xfe, 0xed, 0xfa, 0xce],
+        [0xfe, 0xed, 0xfa, 0xcf],
+        [0xce, 0xfa, 0xed, 0xfe],
+        [0xcf, 0xfa, 0xed, 0xfe],
+    ];
+
+    if header.starts_with(b"\x7fELF") {
+        return Some(BinaryClass::Elf);
+    }
+    if header.starts_with(b"MZ") {
+        return Some(BinaryClass::Pe);
+    }
+    if header.starts_with(b"#!") {
+        return Some(BinaryClass::Script);
+    }
+    if header.len() >= 4 && MACHO_MAGIC.iter().any(|magic| header[..4] == magic[..]) {
+        return Some(BinaryClass::MachO);
+    }
+    // universal binaries share their magic with Java class files, which
+    // have a much larger number after it (the class file version)
+    if header.len() >= 8 && header.starts_with(&[0xca, 0xfe, 0xba, 0xbe]) {
+        let architectures = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        if architectures > 0 && architectures < 20 {
+            return Some(BinaryClass::MachO);
+        }
+    }
+    None
+}
+
+/// Parses a `CLASS=ACTION` rule, e.g. `script=include` or `elf=own-archive`.
+pub fn parse_rule(input: &str) -> Result<(BinaryClass, BinaryAction), String> {
+    let (class, action) = input
+        .split_once('=')
+        .ok_or_else(|| format!("expected CLASS=ACTION, got '{}'", input))?;
+    Ok((class.parse()?, action.parse()?))
+}
+
+impl fmt::Display for BinaryClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryClass::Elf => write!(f, "ELF"),
+            BinaryClass::Pe => write!(f, "PE"),
+            BinaryClass::MachO => write!(f, "Mach-O"),
+            BinaryClass::Script => write!(f, "script"),
+            BinaryClass::Executable => write!(f, "executable"),
+        }
+    }
+}
+
+impl FromStr for BinaryClass {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "elf" => Ok(BinaryClass::Elf),
+            "pe" | "exe" => Ok(BinaryClass::Pe),
+            "macho" | "mach-o" => Ok(BinaryClass::MachO),
+            "script" => Ok(BinaryClass::Script),
+            "executable" | "exec" => Ok(BinaryClass::Executable),
+            _ => Err(format!(
+                "unknown binary class '{}' (expected elf, pe, macho, script or executable)",
+                input
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BinaryAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryAction::Exclude => write!(f, "exclude"),
+            BinaryAction::Include => write!(f, "include"),
+            BinaryAction::OwnArchive => write!(f, "own-archive"),
+        }
+    }
+}
+
+impl FromStr for BinaryAction {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "exclude" => Ok(BinaryAction::Exclude),
+            "include" => Ok(BinaryAction::Include),
+            "own-archive" => Ok(BinaryAction::OwnArchive),
+            _ => Err(format!(
+                "unknown binary action '{}' (expected exclude, include or own-archive)",
+                input
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn magic() {
+        assert_eq!(detect_magic(b"\x7fELF\x02\x01\x01\x00"), Some(BinaryClass::Elf));
+        assert_eq!(detect_magic(b"MZ\x90\x00"), Some(BinaryClass::Pe));
+        assert_eq!(detect_magic(b"#!/bin/sh"), Some(BinaryClass::Script));
+        assert_eq!(detect_magic(&[0xcf, 0xfa, 0xed, 0xfe, 7, 0, 0, 1]), Some(BinaryClass::MachO));
+        assert_eq!(detect_magic(&[0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 2]), Some(BinaryClass::MachO));
+        //a Java class file, version 52
+        assert_eq!(detect_magic(&[0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52]), None);
+        assert_eq!(detect_magic(b"hello"), None);
+        assert_eq!(detect_magic(b""), None);
+    }
+
+    #[test]
+    fn detect_files() {
+        let folder = tempfile::tempdir().unwrap();
+        let detect_file = |name: &str, contents: &[u8]| {
+            let path = folder.path().join(name);
+            fs::write(&path, contents).unwrap();
+            detect(&path, &fs::metadata(&path).unwrap()).unwrap()
+        };
+        assert_eq!(detect_file("setup.EXE", b"not really"), Some(BinaryClass::Pe));
+        assert_eq!(detect_file("run", b"#!/bin/sh\n"), Some(BinaryClass::Script));
+        assert_eq!(detect_file("notes.txt", b"hello"), None);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = folder.path().join("notes.txt");
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            assert_eq!(detect(&path, &fs::metadata(&path).unwrap()).unwrap(), Some(BinaryClass::Executable));
+        }
+    }
+
+    #[test]
+    fn policy() {
+        let policy = BinaryPolicy::new();
+        assert_eq!(policy.action(BinaryClass::Pe), BinaryAction::Exclude);
+        assert_eq!(policy.action(BinaryClass::Elf), BinaryAction::Exclude);
+        assert_eq!(policy.action(BinaryClass::MachO), BinaryAction::Exclude);
+        assert_eq!(policy.action(BinaryClass::Script), BinaryAction::Include);
+        assert_eq!(policy.action(BinaryClass::Executable), BinaryAction::Include);
+
+        let policy = policy.set(BinaryClass::Pe, BinaryAction::Include).set(BinaryClass::Script, BinaryAction::OwnArchive);
+        assert_eq!(policy.action(BinaryClass::Pe), BinaryAction::Include);
+        assert_eq!(policy.action(BinaryClass::Script), BinaryAction::OwnArchive);
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(parse_rule("script=include"), Ok((BinaryClass::Script, BinaryAction::Include)));
+        assert_eq!(parse_rule("Mach-O=own-archive"), Ok((BinaryClass::MachO, BinaryAction::OwnArchive)));
+        assert_eq!(parse_rule("exe=exclude"), Ok((BinaryClass::Pe, BinaryAction::Exclude)));
+        for input in ["script", "script=keep", "jar=include", ""] {
+            assert!(parse_rule(input).is_err(), "{}", input);
+        }
+        for class in [BinaryClass::Elf, BinaryClass::Pe, BinaryClass::MachO, BinaryClass::Script, BinaryClass::Executable] {
+            assert_eq!(class.name().parse(), Ok(class));
+        }
+    }
+}
@@ -0,0 +1,591 @@
+//! A searchable index of all archives written into an archive folder.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use globset::{GlobBuilder, GlobMatcher};
+use rusqlite::{params, Connection, OptionalExtension};
+use zip::ZipArchive;
+
+use crate::error::{Error, Result};
+use crate::format::{self, Format};
+use crate::manifest::{self, Manifest, MANIFEST_NAME};
+use crate::naming;
+use crate::report::ArchiveResult;
+use crate::volume;
+
+/// Name of the catalog database inside the archive folder.
+pub const CATALOG_NAME: &str = "catalog.sqlite";
+
+/// The catalog of an archive folder, an SQLite database that lists every
+/// archive and the files in it.
+pub struct Catalog {
+    path: PathBuf,
+    connection: Connection,
+}
+
+/// Search criteria for [`Catalog::find`]. Criteria that are left out match
+/// every file.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    name: Option<String>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    after: Option<i64>,
+    before: Option<i64>,
+}
+
+/// A file found in the catalog.
+#[derive(Debug, Clone)]
+pub struct CatalogEntry {
+    /// Path of the archive holding the file.
+    pub archive: PathBuf,
+    /// When the archive was written.
+    pub archived: DateTime<Local>,
+    /// Name of the entry in the archive.
+    pub name: String,
+    /// Path the file was archived from, if known.
+    pub path: Option<PathBuf>,
+    pub size: u64,
+    pub modified: Option<DateTime<Local>>,
+    pub sha256: Option<String>,
+}
+
+/// An archive as it is stored in the catalog.
+struct Record {
+    file_name: String,
+    format: Format,
+    created: i64,
+    mode: Option<String>,
+    label: Option<String>,
+    size: u64,
+    files: Vec<RecordedFile>,
+}
+
+struct RecordedFile {
+    name: String,
+    path: Option<String>,
+    size: u64,
+    modified: Option<i64>,
+    sha256: Option<String>,
+    /// Whether the entry passed the verification after writing.
+    verified: bool,
+}
+
+impl Catalog {
+    /// Opens the catalog of `archive_folder`, creating it if needed.
+    pub fn open(archive_folder: &Path) -> Result<Self> {
+        let path = archive_folder.join(CATALOG_NAME);
+        let connection = Connection::open(&path).map_err(|err| Error::catalog(&path, err))?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS archives (
+                    id INTEGER PRIMARY KEY,
+                    file_name TEXT NOT NULL UNIQUE,
+                    format TEXT NOT NULL,
+                    created INTEGER NOT NULL,
+                    mode TEXT,
+                    label TEXT,
+                    size INTEGER NOT NULL
+                );
+                CREATE TABLE IF NOT EXISTS entries (
+                    archive_id INTEGER NOT NULL REFERENCES archives(id) ON DELETE CASCADE,
+                    name TEXT NOT NULL,
+                    file_name TEXT NOT NULL,
+                    path TEXT,
+                    size INTEGER NOT NULL,
+                    modified INTEGER,
+                    sha256 TEXT,
+                    verified INTEGER NOT NULL DEFAULT 0
+                );
+                CREATE INDEX IF NOT EXISTS entries_file_name ON entries(file_name);
+                PRAGMA foreign_keys = ON;",
+            )
+            .map_err(|err| Error::catalog(&path, err))?;
+
+        //catalogs written before entries were marked as verified
+        let verified_column: bool = connection
+            .query_row("SELECT COUNT(*) FROM pragma_table_info('entries') WHERE name = 'verified'", [], |row| row.get(0))
+            .map_err(|err| Error::catalog(&path, err))?;
+        if !verified_column {
+            connection
+                .execute("ALTER TABLE entries ADD COLUMN verified INTEGER NOT NULL DEFAULT 0", [])
+                .map_err(|err| Error::catalog(&path, err))?;
+        }
+        Ok(Catalog { path, connection })
+    }
+
+    /// Adds a freshly written archive, with the files that passed its
+    /// verification if it was verified already.
+    pub fn record(&mut self, result: &ArchiveResult) -> Result<()> {
+        let mut record = Record::from_manifest(&result.path, result.format, result.archive_size, &result.manifest);
+        let verified: HashSet<&str> = verified_names(result).collect();
+        for file in &mut record.files {
+            file.verified = verified.contains(file.name.as_str());
+        }
+        self.insert(record)
+    }
+
+    /// Marks the files of a recorded archive that passed its verification,
+    /// see [`ArchiveResult::verify`].
+    pub fn record_verification(&mut self, result: &ArchiveResult) -> Result<()> {
+        let transaction = self.connection.transaction().map_err(|err| Error::catalog(&self.path, err))?;
+        {
+            let mut statement = transaction
+                .prepare(
+                    "UPDATE entries SET verified = 1
+                     WHERE archive_id = (SELECT id FROM archives WHERE file_name = ?1) AND name = ?2",
+                )
+                .map_err(|err| Error::catalog(&self.path, err))?;
+            for name in verified_names(result) {
+                statement
+                    .execute(params![archive_file_name(&result.path), name])
+                    .map_err(|err| Error::catalog(&self.path, err))?;
+            }
+        }
+        transaction.commit().map_err(|err| Error::catalog(&self.path, err))
+    }
+
+    /// Adds an archive written before the catalog existed. Returns `false`
+    /// if the archive is already in the catalog.
+    ///
+    /// Archives with a manifest are imported from it, other archives from
+    /// their entries, dated by the time in their file name. So are encrypted
+    /// archives, whose entry names can be read without the password.
+    /// Archives encrypted to public keys can't be read at all and are
+    /// imported without their files.
+    pub fn import(&mut self, archive: &Path) -> Result<bool> {
+        let format = Format::from_path(archive).ok_or_else(|| Error::UnknownFormat(archive.to_path_buf()))?;
+        if self.contains(archive)? {
+            return Ok(false);
+        }
+        let size = volume::total_size(archive).map_err(|err| Error::io(archive, err))?;
+
+        match manifest::read_manifest(archive) {
+            Ok(Some(manifest)) => {
+                self.insert(Record::from_manifest(archive, format, size, &manifest))?;
+                return Ok(true);
+            }
+            Ok(None) | Err(Error::PasswordRequired(_) | Error::IdentityRequired(_)) => {}
+            Err(err) => return Err(err),
+        }
+
+        let files = if format::is_encrypted(archive) {
+            Vec::new()
+        } else {
+            read_entries(archive, format).map_err(|err| Error::io(archive, err))?
+        };
+        let created = created_from_name(archive)
+            .or_else(|| fs::metadata(volume::volumes(archive).pop()?).and_then(|meta| meta.modified()).ok().map(DateTime::<Local>::from))
+            .unwrap_or_else(Local::now);
+        self.insert(Record {
+            file_name: archive_file_name(archive),
+            format,
+            created: created.timestamp(),
+            mode: None,
+            label: None,
+            size,
+            files,
+        })?;
+        Ok(true)
+    }
+
+    /// Whether the catalog lists an archive with the file name of `archive`.
+    pub fn contains(&self, archive: &Path) -> Result<bool> {
+        let file_name = archive_file_name(archive);
+        self.connection
+            .query_row("SELECT 1 FROM archives WHERE file_name = ?1", [file_name], |_| Ok(()))
+            .optional()
+            .map(|found| found.is_some())
+            .map_err(|err| Error::catalog(&self.path, err))
+    }
+
+    /// When `archive` was written and its label, if it is in the catalog.
+    /// Archives imported without a manifest have no label.
+    pub(crate) fn recorded(&self, archive: &Path) -> Result<Option<(DateTime<Local>, Option<String>)>> {
+        self.connection
+            .query_row(
+                "SELECT created, label FROM archives WHERE file_name = ?1",
+                [archive_file_name(archive)],
+                |row| Ok((timestamp(row.get(0)?), row.get(1)?)),
+            )
+            .optional()
+            .map_err(|err| Error::catalog(&self.path, err))
+    }
+
+    /// Removes an archive and its files from the catalog.
+    pub fn remove(&mut self, archive: &Path) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM archives WHERE file_name = ?1", [archive_file_name(archive)])
+            .map(|_| ())
+            .map_err(|err| Error::catalog(&self.path, err))
+    }
+
+    /// Whether one of the archives holds the file at `path` as it is now,
+    /// going by its size and modification time, and its entry passed the
+    /// verification.
+    pub fn holds(&self, path: &Path, size: u64, modified: DateTime<Local>) -> Result<bool> {
+        self.connection
+            .query_row(
+                "SELECT 1 FROM entries WHERE path = ?1 AND size = ?2 AND modified = ?3 AND verified = 1",
+                params![path.to_string_lossy(), size as i64, modified.timestamp()],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|found| found.is_some())
+            .map_err(|err| Error::catalog(&self.path, err))
+    }
+
+    /// Files matching `query`, oldest archive first.
+    pub fn find(&self, query: &Query) -> Result<Vec<CatalogEntry>> {
+        let matcher = query.matcher()?;
+        let folder = self.path.parent().unwrap_or(Path::new(""));
+
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT archives.file_name, archives.created, entries.name, entries.path, entries.size,
+                        entries.modified, entries.sha256
+                 FROM entries JOIN archives ON archives.id = entries.archive_id
+                 WHERE (?1 IS NULL OR entries.size >= ?1) AND (?2 IS NULL OR entries.size <= ?2)
+                   AND (?3 IS NULL OR archives.created >= ?3) AND (?4 IS NULL OR archives.created < ?4)
+                 ORDER BY archives.created, archives.file_name, entries.name",
+            )
+            .map_err(|err| Error::catalog(&self.path, err))?;
+
+        let rows = statement
+            .query_map(
+                params![
+                    query.min_size.map(|size| size as i64),
+                    query.max_size.map(|size| size as i64),
+                    query.after,
+                    query.before
+                ],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, i64>(4)?,
+                        row.get::<_, Option<i64>>(5)?,
+                        row.get::<_, Option<String>>(6)?,
+                    ))
+                },
+            )
+            .map_err(|err| Error::catalog(&self.path, err))?;
+
+        let mut found = Vec::new();
+        for row in rows {
+            let (archive, created, name, path, size, modified, sha256) = row.map_err(|err| Error::catalog(&self.path, err))?;
+            if !query.matches(matcher.as_ref(), &name) {
+                continue;
+            }
+            found.push(CatalogEntry {
+                archive: folder.join(archive),
+                archived: timestamp(created),
+                name,
+                path: path.map(PathBuf::from),
+                size: size as u64,
+                modified: modified.map(timestamp),
+                sha256,
+            });
+        }
+        Ok(found)
+    }
+
+    fn insert(&mut self, record: Record) -> Result<()> {
+        let transaction = self.connection.transaction().map_err(|err| Error::catalog(&self.path, err))?;
+        // an archive written again within the same minute replaces the old one
+        transaction
+            .execute("DELETE FROM archives WHERE file_name = ?1", [&record.file_name])
+            .map_err(|err| Error::catalog(&self.path, err))?;
+        transaction
+            .execute(
+                "INSERT INTO archives (file_name, format, created, mode, label, size) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    record.file_name,
+                    record.format.to_string(),
+                    record.created,
+                    record.mode,
+                    record.label,
+                    record.size as i64
+                ],
+            )
+            .map_err(|err| Error::catalog(&self.path, err))?;
+        let archive_id = transaction.last_insert_rowid();
+
+        {
+            let mut statement = transaction
+                .prepare(
+                    "INSERT INTO entries (archive_id, name, file_name, path, size, modified, sha256, verified)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                )
+                .map_err(|err| Error::catalog(&self.path, err))?;
+            for file in &record.files {
+                statement
+                    .execute(params![
+                        archive_id,
+                        file.name,
+                        file.name.rsplit('/').next().unwrap_or(&file.name),
+                        file.path,
+                        file.size as i64,
+                        file.modified,
+                        file.sha256,
+                        file.verified,
+                    ])
+                    .map_err(|err| Error::catalog(&self.path, err))?;
+            }
+        }
+        transaction.commit().map_err(|err| Error::catalog(&self.path, err))
+    }
+}
+
+impl Record {
+    fn from_manifest(archive: &Path, format: Format, size: u64, manifest: &Manifest) -> Self {
+        let files = manifest
+            .files
+            .iter()
+            .map(|file| RecordedFile {
+                name: file.name.clone(),
+                path: Some(file.path.to_string_lossy().to_string()),
+                size: file.size,
+                modified: file.modified.as_deref().and_then(parse_timestamp),
+                sha256: Some(file.sha256.clone()),
+                verified: false,
+            })
+            .collect();
+
+        Record {
+            file_name: archive_file_name(archive),
+            format,
+            created: parse_timestamp(&manifest.created).unwrap_or_else(|| Local::now().timestamp()),
+            mode: Some(manifest.mode.clone()),
+            label: Some(manifest.label()),
+            size,
+            files,
+        }
+    }
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Query::default()
+    }
+
+    /// Only files whose name contains `pattern`, ignoring case. Patterns
+    /// with `*`, `?` or `[` are matched as a glob against the whole name.
+    pub fn name(mut self, pattern: impl Into<String>) -> Self {
+        self.name = Some(pattern.into());
+        self
+    }
+
+    /// Only files of at least `size` bytes.
+    pub fn min_size(mut self, size: u64) -> Self {
+        self.min_size = Some(size);
+        self
+    }
+
+    /// Only files of at most `size` bytes.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Only files archived on or after `date`.
+    pub fn archived_after(mut self, date: NaiveDate) -> Self {
+        self.after = Some(start_of_day(date));
+        self
+    }
+
+    /// Only files archived on or before `date`.
+    pub fn archived_before(mut self, date: NaiveDate) -> Self {
+        self.before = date.succ_opt().map(start_of_day);
+        self
+    }
+
+    fn matcher(&self) -> Result<Option<GlobMatcher>> {
+        match &self.name {
+            Some(pattern) if pattern.contains(['*', '?', '[']) => GlobBuilder::new(pattern)
+                .case_insensitive(true)
+                .literal_separator(false)
+                .build()
+                .map(|glob| Some(glob.compile_matcher()))
+                .map_err(|err| Error::InvalidPattern(err.to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Globs are matched against the file name and the full entry name, so
+    /// `*.csv` finds files in subfolders as well.
+    fn matches(&self, matcher: Option<&GlobMatcher>, name: &str) -> bool {
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        match (&self.name, matcher) {
+            (None, _) => true,
+            (Some(_), Some(matcher)) => matcher.is_match(file_name) || matcher.is_match(name),
+            (Some(pattern), None) => file_name.to_lowercase().contains(&pattern.to_lowercase()),
+        }
+    }
+}
+
+/// Names of the entries of `result` that passed its verification.
+fn verified_names(result: &ArchiveResult) -> impl Iterator<Item = &str> {
+    result
+        .files
+        .iter()
+        .filter(|file| result.verification.as_ref().is_some_and(|verification| verification.is_verified(&file.path)))
+        .map(|file| file.name.as_str())
+}
+
+fn archive_file_name(archive: &Path) -> String {
+    archive.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn parse_timestamp(rfc3339: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(rfc3339).ok().map(|time| time.timestamp())
+}
+
+fn timestamp(seconds: i64) -> DateTime<Local> {
+    Local.timestamp_opt(seconds, 0).earliest().unwrap_or_else(Local::now)
+}
+
+fn start_of_day(date: NaiveDate) -> i64 {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|midnight| midnight.timestamp())
+        .unwrap_or_else(|| midnight.and_utc().timestamp())
+}
+
+/// Reads the time from an archive name like `csv_archive_2024_3_1_14_5.zip`.
+fn created_from_name(archive: &Path) -> Option<DateTime<Local>> {
+    let name = format::archive_stem(archive)?;
+    let (_, time) = naming::parse_default_name(&name)?;
+    Local.from_local_datetime(&time).earliest()
+}
+
+/// The file entries of an archive without a manifest.
+fn read_entries(archive: &Path, format: Format) -> std::io::Result<Vec<RecordedFile>> {
+    let file = volume::open(archive)?;
+    let mut entries = Vec::new();
+
+    if format.is_tar() {
+        let mut tar = tar::Archive::new(format::tar_reader(format, file)?);
+        for entry in tar.entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            if !header.entry_type().is_file() {
+                continue;
+            }
+            entries.push(RecordedFile {
+                name: String::from_utf8_lossy(&entry.path_bytes()).to_string(),
+                path: None,
+                size: header.size()?,
+                modified: header.mtime().ok().map(|mtime| mtime as i64),
+                sha256: None,
+                verified: false,
+            });
+        }
+    } else {
+        let mut zip = ZipArchive::new(file)?;
+        for index in 0..zip.len() {
+            let entry = zip.by_index_raw(index)?;
+            if entry.is_dir() || entry.name() == MANIFEST_NAME {
+                continue;
+            }
+            let modified = entry
+                .last_modified()
+                .and_then(|modified| NaiveDateTime::try_from(modified).ok())
+                .and_then(|modified| Local.from_local_datetime(&modified).earliest())
+                .map(|modified| modified.timestamp());
+            entries.push(RecordedFile {
+                name: entry.name().to_string(),
+                path: None,
+                size: entry.size(),
+                modified,
+                sha256: None,
+                verified: false,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Archives in `archive_folder`, sorted by name. Split archives are listed
+/// once, see [`volume`].
+pub fn archives_in(archive_folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut archives = Vec::new();
+    for entry in fs::read_dir(archive_folder).map_err(|err| Error::io(archive_folder, err))? {
+        let path = entry.map_err(|err| Error::io(archive_folder, err))?.path();
+        if path.is_file() && Format::from_path(&path).is_some() {
+            archives.push(path);
+        } else if path.to_string_lossy().ends_with(".001") {
+            //split tarballs are listed once, by the name without the number
+            let archive = volume::archive_of(&path);
+            if archive != path && !archive.exists() {
+                archives.push(archive);
+            }
+        }
+    }
+    archives.sort();
+    Ok(archives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded_file(path: &str, verified: bool) -> RecordedFile {
+        RecordedFile {
+            name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            path: Some(path.to_string()),
+            size: 10,
+            modified: Some(1_700_000_000),
+            sha256: None,
+            verified,
+        }
+    }
+
+    #[test]
+    fn holds_only_verified_files() {
+        let folder = tempfile::tempdir().unwrap();
+        let mut catalog = Catalog::open(folder.path()).unwrap();
+        catalog
+            .insert(Record {
+                file_name: "full.zip".to_string(),
+                format: Format::Zip,
+                created: 1_700_000_000,
+                mode: Some("all".to_string()),
+                label: Some("full".to_string()),
+                size: 100,
+                files: vec![recorded_file("/data/good.txt", true), recorded_file("/data/bad.txt", false)],
+            })
+            .unwrap();
+
+        let modified = timestamp(1_700_000_000);
+        assert!(catalog.holds(Path::new("/data/good.txt"), 10, modified).unwrap());
+        assert!(!catalog.holds(Path::new("/data/good.txt"), 11, modified).unwrap());
+        //an entry that failed verification is archived again
+        assert!(!catalog.holds(Path::new("/data/bad.txt"), 10, modified).unwrap());
+        assert!(catalog.contains(Path::new("/elsewhere/full.zip")).unwrap());
+    }
+
+    #[test]
+    fn adds_the_verified_column() {
+        let folder = tempfile::tempdir().unwrap();
+        let connection = Connection::open(folder.path().join(CATALOG_NAME)).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE entries (archive_id INTEGER NOT NULL, name TEXT NOT NULL, file_name TEXT NOT NULL,
+                 path TEXT, size INTEGER NOT NULL, modified INTEGER, sha256 TEXT);",
+            )
+            .unwrap();
+        drop(connection);
+
+        let catalog = Catalog::open(folder.path()).unwrap();
+        assert!(!catalog.holds(Path::new("/data/a.txt"), 1, timestamp(0)).unwrap());
+    }
+}
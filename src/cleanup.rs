@@ -0,0 +1,75 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::report::{ArchiveReport, ArchiveResult};
+use crate::trash;
+
+/// What happened to an archived file in the post-archive step.
+#[derive(Debug)]
+pub enum Outcome {
+    Deleted,
+    /// The file was moved to the trash, to the given location.
+    Trashed(PathBuf),
+    /// The file was left in place, e.g. because it failed verification.
+    Kept(String),
+    Failed(io::Error),
+}
+
+/// Outcome for a single file of the manifest.
+#[derive(Debug)]
+pub struct CleanupResult {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+}
+
+/// Permanently deletes the archived files.
+///
+/// Only files in the manifests of the report are considered, and only those
+/// whose archive was verified and whose entry passed the check are deleted.
+pub fn delete_archived(report: &ArchiveReport) -> Vec<CleanupResult> {
+    remove_archived(report, |path| fs::remove_file(path).map(|_| Outcome::Deleted))
+}
+
+/// Moves the archived files to the trash, see [`trash::move_to_trash`].
+///
+/// The same files as with [`delete_archived`] are considered.
+pub fn trash_archived(report: &ArchiveReport) -> Vec<CleanupResult> {
+    remove_archived(report, |path| trash::move_to_trash(path).map(Outcome::Trashed))
+}
+
+fn remove_archived(report: &ArchiveReport, remove: impl Fn(&Path) -> io::Result<Outcome>) -> Vec<CleanupResult> {
+    let mut results = Vec::new();
+    for archive in report.written() {
+        for file in &archive.files {
+            let outcome = match verification_failure(archive, &file.path) {
+                Some(reason) => Outcome::Kept(reason),
+                None => remove(&file.path).unwrap_or_else(Outcome::Failed),
+            };
+            results.push(CleanupResult {
+                path: file.path.clone(),
+                outcome,
+            });
+        }
+    }
+    results
+}
+
+/// Why `path` must not be removed, or `None` if its entry was verified.
+fn verification_failure(archive: &ArchiveResult, path: &Path) -> Option<String> {
+    let verification = match &archive.verification {
+        Some(verification) => verification,
+        None => return Some("archive was not verified".to_string()),
+    };
+    if verification.is_verified(path) {
+        return None;
+    }
+
+    let reason = verification
+        .failed
+        .iter()
+        .find(|(failed, _)| failed == path)
+        .map(|(_, reason)| reason.as_str())
+        .unwrap_or("not checked");
+    Some(format!("failed verification: {}", reason))
+}
@@ -0,0 +1,510 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use the_archivist::{
+    binary, policy, AgeFilter, BinaryAction, BinaryClass, Compression, CompressionPolicy, Conflict, Format, NameTemplate, Recipient,
+    Retention, TimeSpec, Timestamp,
+};
+use the_archivist::volume;
+
+/// Command line interface of the archivist.
+///
+/// Without a subcommand the tool falls back to the interactive prompts.
+#[derive(Parser, Debug)]
+#[command(name = "the_archivist", version, about = "Archive files in a folder and optionally delete them after archiving")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Options of the archives written by the interactive prompts; the
+    /// subcommands writing archives take their own
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+/// How the archives are written, for the commands writing archives.
+#[derive(Args, Debug, Clone)]
+pub struct OutputArgs {
+    /// Compression of the archives: store, deflate, bzip2 or zstd, optionally
+    /// with a level, e.g. deflate:9 or zstd:19 [default: deflate]
+    #[arg(short, long, env = "ARCHIVIST_COMPRESSION", value_name = "METHOD[:LEVEL]")]
+    pub compression: Option<Compression>,
+
+    /// Format of the archives: zip, tar.gz, tar.zst or tar.xz [default: zip]
+    #[arg(short, long)]
+    pub format: Option<Format>,
+
+    /// Split archives into volumes of at most this size, e.g. 4G or 700M:
+    /// name.z01, name.z02, ... name.zip for zip, name.tar.gz.001, ... for
+    /// tarballs
+    #[arg(long, value_parser = parse_volume_size, value_name = "SIZE")]
+    pub volume_size: Option<u64>,
+
+    /// Encrypt the zip entries with AES-256. The password is read from
+    /// --password-fd, the ARCHIVIST_PASSWORD environment variable or a
+    /// hidden prompt
+    #[arg(long)]
+    pub encrypt: bool,
+
+    /// Read the password of encrypted archives from this file descriptor,
+    /// e.g. --password-fd 3 3< secret.txt
+    #[arg(long, value_name = "FD")]
+    pub password_fd: Option<i32>,
+
+    /// Encrypt the archives to this public key (age1...), so only its
+    /// identity file can read them. Can be given multiple times
+    #[arg(short = 'R', long, value_name = "PUBLIC_KEY")]
+    pub recipient: Vec<Recipient>,
+
+    /// Encrypt the archives to the public keys in this file, one per line
+    #[arg(long, value_name = "FILE")]
+    pub recipients_file: Option<PathBuf>,
+
+    /// Number of archives written at the same time [default: one per CPU
+    /// core]
+    #[arg(short, long, value_parser = parse_jobs, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Compression for specific file types, overriding the built-in table that
+    /// stores already compressed formats, e.g. jpg=deflate,log=zstd:19
+    #[arg(long, value_delimiter = ',', value_parser = policy::parse_rule, value_name = "EXT=METHOD")]
+    pub type_compression: Vec<(String, Compression)>,
+
+    /// Test-compress the first block of each file and store the file as is
+    /// if it doesn't compress
+    #[arg(long)]
+    pub sample_compression: bool,
+}
+
+// parsed once per run, so the size of the archive arguments doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Archive the files of a folder into the folder's archive/ subfolder
+    Archive(ArchiveArgs),
+    /// Extract an archive back into the folder it was made from
+    Restore(RestoreArgs),
+    /// Search the catalog for the archives holding a file
+    Find(FindArgs),
+    /// Add archives written before the catalog existed to the catalog
+    Import(ImportArgs),
+    /// Run a profile from the config files
+    Run(RunArgs),
+    /// Check the config files and list their profiles
+    Validate(ValidateArgs),
+    /// Keep running and archive the files of profiles as they arrive
+    Watch(WatchArgs),
+    /// Remove old archives from an archive folder by retention rules
+    Prune(PruneArgs),
+    /// Create an identity file and print its public key, for --recipient
+    Keygen(KeygenArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ArchiveArgs {
+    /// Folder containing the files to archive
+    pub folder: PathBuf,
+
+    /// How the files are grouped into archives
+    #[arg(long, value_enum)]
+    pub mode: Mode,
+
+    /// File endings to archive, separated by comma (by-extension mode)
+    #[arg(long, value_delimiter = ',', required_if_eq("mode", "by-extension"))]
+    pub ext: Vec<String>,
+
+    /// Pattern the file names have to match (by-name mode): a string the
+    /// names contain, or glob:PATTERN, re:REGEX, prefix:TEXT or suffix:TEXT.
+    /// Can be given multiple times
+    #[arg(long, required_if_eq("mode", "by-name"), value_name = "PATTERN")]
+    pub name: Vec<String>,
+
+    /// Pattern of file names to leave out (by-name mode), same syntax as
+    /// --name. Can be given multiple times
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude_name: Vec<String>,
+
+    /// Match the --name and --exclude-name patterns ignoring case
+    #[arg(short = 'i', long)]
+    pub ignore_case: bool,
+
+    /// Also archive the files of all subfolders, keeping their relative paths
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Only archive files older than a date (2024-03-01) or an age (90d);
+    /// ages take a unit of s, m, h, d, w or y
+    #[arg(long, value_name = "TIME")]
+    pub older_than: Option<TimeSpec>,
+
+    /// Only archive files newer than a date (2024-03-01) or an age (90d)
+    #[arg(long, value_name = "TIME")]
+    pub newer_than: Option<TimeSpec>,
+
+    /// Timestamp the age of the files is measured by: mtime, atime or ctime
+    #[arg(long, default_value_t = Timestamp::Modified, value_name = "TIMESTAMP")]
+    pub time: Timestamp,
+
+    /// Only archive files of at least this size, e.g. 500M
+    #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+    pub min_size: Option<u64>,
+
+    /// Only archive files of at most this size, e.g. 10K
+    #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+    pub max_size: Option<u64>,
+
+    /// What happens to executables of a class: exclude, include or
+    /// own-archive. elf, pe and macho are excluded by default, script and
+    /// executable included. Classes are elf, pe, macho, script (#! line) and
+    /// executable (exec bit), e.g. script=exclude,elf=own-archive
+    #[arg(long, value_delimiter = ',', value_parser = binary::parse_rule, value_name = "CLASS=ACTION")]
+    pub binaries: Vec<(BinaryClass, BinaryAction)>,
+
+    /// Also leave out the files listed in .gitignore files
+    #[arg(long)]
+    pub gitignore: bool,
+
+    /// Folder to write the archives to [default: the archive/ subfolder]
+    #[arg(long, value_name = "FOLDER")]
+    pub destination: Option<PathBuf>,
+
+    /// File name of the archives, e.g. {folder}_{label}_{date}; placeholders
+    /// are label, folder, date, time, year, month, day, hour and minute
+    #[arg(long, value_name = "TEMPLATE")]
+    pub name_template: Option<NameTemplate>,
+
+    /// Move the archived files to the trash after archiving
+    #[arg(long, conflicts_with = "delete")]
+    pub trash: bool,
+
+    /// Permanently delete the archived files after archiving
+    #[arg(long)]
+    pub delete: bool,
+
+    /// Only print which archives would be created and which files would go
+    /// into them, without writing or deleting anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Do not ask for confirmation before deleting permanently
+    #[arg(short, long, requires = "delete")]
+    pub yes: bool,
+
+    #[command(flatten)]
+    pub retention: RetentionArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+/// Retention rules; archives kept by any of them are kept.
+#[derive(Args, Debug)]
+pub struct RetentionArgs {
+    /// Prune the archive folder afterwards, keeping the newest N archives of
+    /// each label (full, csv, ...)
+    #[arg(long, value_name = "N")]
+    pub keep_last: Option<usize>,
+
+    /// Keep the newest archive of each of the last N days
+    #[arg(long, value_name = "N")]
+    pub keep_daily: Option<usize>,
+
+    /// Keep the newest archive of each of the last N weeks
+    #[arg(long, value_name = "N")]
+    pub keep_weekly: Option<usize>,
+
+    /// Keep the newest archive of each of the last N months
+    #[arg(long, value_name = "N")]
+    pub keep_monthly: Option<usize>,
+
+    /// Keep the archives newer than a date or an age like 90d; on its own,
+    /// removes the archives older than that
+    #[arg(long, value_name = "TIME")]
+    pub max_age: Option<TimeSpec>,
+}
+
+#[derive(Args, Debug)]
+pub struct RestoreArgs {
+    /// Archive to extract
+    pub archive: PathBuf,
+
+    /// Folder to extract into [default: the folder above the archive/ folder]
+    #[arg(short, long)]
+    pub target: Option<PathBuf>,
+
+    /// What to do with files that already exist: skip, overwrite, rename or
+    /// keep-newer
+    #[arg(long, default_value_t = Conflict::Skip)]
+    pub conflict: Conflict,
+
+    /// Identity file decrypting archives encrypted to public keys (*.age).
+    /// Can be given multiple times
+    #[arg(short, long, value_name = "FILE")]
+    pub identity: Vec<PathBuf>,
+
+    /// Also restore the setuid, setgid and sticky bits of files
+    #[arg(long)]
+    pub special_bits: bool,
+
+    /// Read the password of an encrypted archive from this file descriptor,
+    /// e.g. --password-fd 3 3< secret.txt
+    #[arg(long, value_name = "FD")]
+    pub password_fd: Option<i32>,
+}
+
+#[derive(Args, Debug)]
+pub struct FindArgs {
+    /// Folder that was archived, or its archive folder
+    pub folder: PathBuf,
+
+    /// Part of the file name, or a glob like report_*.csv
+    pub pattern: Option<String>,
+
+    /// Only files of at least this size, e.g. 500K or 2G
+    #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+    pub min_size: Option<u64>,
+
+    /// Only files of at most this size, e.g. 500K or 2G
+    #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+    pub max_size: Option<u64>,
+
+    /// Only files archived on or after this date (YYYY-MM-DD)
+    #[arg(long, value_parser = parse_date, value_name = "DATE")]
+    pub after: Option<NaiveDate>,
+
+    /// Only files archived on or before this date (YYYY-MM-DD)
+    #[arg(long, value_parser = parse_date, value_name = "DATE")]
+    pub before: Option<NaiveDate>,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Folder that was archived, or its archive folder
+    pub folder: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Name of the profile
+    pub profile: String,
+
+    /// Config file to read the profile from [default: ./.archivist.toml, then
+    /// ~/.config/the_archivist/config.toml]
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Only print which archives would be created and which files would go
+    /// into them, without writing or deleting anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Do not ask for confirmation if the profile deletes the files
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Options overriding the ones of the profile
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// Config file to check [default: ./.archivist.toml and
+    /// ~/.config/the_archivist/config.toml]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    /// Names of the profiles to watch
+    #[arg(required = true)]
+    pub profiles: Vec<String>,
+
+    /// Config file to read the profiles from [default: ./.archivist.toml,
+    /// then ~/.config/the_archivist/config.toml]
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Allow profiles that delete the files permanently
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Options overriding the ones of the profiles
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PruneArgs {
+    /// Folder that was archived, or its archive folder
+    pub folder: PathBuf,
+
+    #[command(flatten)]
+    pub retention: RetentionArgs,
+
+    /// Only print which archives would be removed
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Do not ask for confirmation before removing the archives
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct KeygenArgs {
+    /// File to write the identity to [default: print it]
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+impl RetentionArgs {
+    /// The retention rules of the arguments, if any were given.
+    pub fn retention(&self) -> Option<Retention> {
+        let mut retention = Retention::new();
+        if let Some(count) = self.keep_last {
+            retention = retention.keep_last(count);
+        }
+        if let Some(count) = self.keep_daily {
+            retention = retention.keep_daily(count);
+        }
+        if let Some(count) = self.keep_weekly {
+            retention = retention.keep_weekly(count);
+        }
+        if let Some(count) = self.keep_monthly {
+            retention = retention.keep_monthly(count);
+        }
+        if let Some(age) = self.max_age {
+            retention = retention.max_age(age);
+        }
+        Some(retention).filter(|retention| !retention.is_empty())
+    }
+}
+
+impl OutputArgs {
+    /// The compression policy of --type-compression and
+    /// --sample-compression.
+    pub fn policy(&self) -> CompressionPolicy {
+        let policy = CompressionPolicy::default().sample(self.sample_compression);
+        self.type_compression
+            .iter()
+            .fold(policy, |policy, (extension, compression)| policy.set(extension, *compression))
+    }
+}
+
+impl ArchiveArgs {
+    /// The age filter of the arguments, if an age was given.
+    pub fn age_filter(&self) -> Option<AgeFilter> {
+        if self.older_than.is_none() && self.newer_than.is_none() {
+            return None;
+        }
+        let mut filter = AgeFilter::new().timestamp(self.time);
+        if let Some(time) = self.older_than {
+            filter = filter.older_than(time);
+        }
+        if let Some(time) = self.newer_than {
+            filter = filter.newer_than(time);
+        }
+        Some(filter)
+    }
+}
+
+/// What happens to the archived files once the archives are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AfterArchive {
+    Keep,
+    Trash,
+    Delete,
+}
+
+/// The four archiving options of the interactive menu.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// 1 - All files in one archive
+    All,
+    /// 2 - Separate archives for each file type
+    PerType,
+    /// 3 - Selected file types, one archive per file type
+    ByExtension,
+    /// 4 - All files containing a specific string in the name
+    ByName,
+}
+
+/// Parses a size in bytes with an optional unit: `1500`, `500K`, `1.5M`,
+/// `2G` or `1T` (powers of 1024).
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size '{}' (expected e.g. 500K or 2G)", input))?;
+    let factor: u64 = match unit.to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("unknown size unit '{}' (expected K, M, G or T)", unit)),
+    };
+    if number < 0.0 {
+        return Err(format!("invalid size '{}'", input));
+    }
+    Ok((number * factor as f64) as u64)
+}
+
+/// Parses a volume size, see [`parse_size`].
+pub fn parse_volume_size(input: &str) -> Result<u64, String> {
+    let size = parse_size(input)?;
+    if size < volume::MIN_VOLUME_SIZE {
+        return Err(format!("volume size '{}' is too small (at least 64K)", input));
+    }
+    Ok(size)
+}
+
+/// Parses the number of archives written at the same time.
+pub fn parse_jobs(input: &str) -> Result<usize, String> {
+    match input.trim().parse() {
+        Ok(jobs) if jobs > 0 => Ok(jobs),
+        _ => Err(format!("invalid number of jobs '{}' (expected at least 1)", input)),
+    }
+}
+
+fn parse_date(input: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(input.trim(), "%Y-%m-%d").map_err(|_| format!("invalid date '{}' (expected YYYY-MM-DD)", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("1500"), Ok(1500));
+        assert_eq!(parse_size("500K"), Ok(500 * 1024));
+        assert_eq!(parse_size("500kb"), Ok(500 * 1024));
+        assert_eq!(parse_size("1.5M"), Ok(1536 * 1024));
+        assert_eq!(parse_size(" 2 GiB "), Ok(2 << 30));
+        assert_eq!(parse_size("1T"), Ok(1 << 40));
+        assert_eq!(parse_size("0"), Ok(0));
+        for input in ["", "K", "-1K", "12Q", "1e3", "inf", "1.2.3M"] {
+            assert!(parse_size(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn volume_sizes() {
+        assert_eq!(parse_volume_size("64K"), Ok(volume::MIN_VOLUME_SIZE));
+        assert_eq!(parse_volume_size("100M"), Ok(100 << 20));
+        assert!(parse_volume_size("63K").is_err());
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(parse_date(" 2024-03-01 "), Ok(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()));
+        assert!(parse_date("01.03.2024").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+use std::str::FromStr;
+
+use zip::CompressionMethod;
+
+/// Compression method and level used for the entries of an archive.
+///
+/// Parsed from strings like `store`, `deflate`, `deflate:9`, `bzip2:6`,
+/// `zstd:19` or `xz:9`; without a level the method's default level is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Stored,
+    Deflate(Option<i64>),
+    Bzip2(Option<i64>),
+    Zstd(Option<i64>),
+    Xz(Option<i64>),
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Deflate(None)
+    }
+}
+
+impl Compression {
+    /// The zip compression method.
+    pub fn method(&self) -> CompressionMethod {
+        match self {
+            Compression::Stored => CompressionMethod::Stored,
+            Compression::Deflate(_) => CompressionMethod::Deflated,
+            Compression::Bzip2(_) => CompressionMethod::Bzip2,
+            Compression::Zstd(_) => CompressionMethod::Zstd,
+            Compression::Xz(_) => CompressionMethod::Xz,
+        }
+    }
+
+    /// The compression level, `None` for the method's default.
+    pub fn level(&self) -> Option<i64> {
+        match self {
+            Compression::Stored => None,
+            Compression::Deflate(level) | Compression::Bzip2(level) | Compression::Zstd(level) | Compression::Xz(level) => *level,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Compression::Stored => "store",
+            Compression::Deflate(_) => "deflate",
+            Compression::Bzip2(_) => "bzip2",
+            Compression::Zstd(_) => "zstd",
+            Compression::Xz(_) => "xz",
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level() {
+            Some(level) => write!(f, "{} (level {})", self.name(), level),
+            None => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (method, level) = match input.trim().split_once(':') {
+            Some((method, level)) => {
+                let level = level
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| format!("invalid compression level '{}'", level))?;
+                (method.trim(), Some(level))
+            }
+            None => (input.trim(), None),
+        };
+
+        let (compression, levels) = match method.to_ascii_lowercase().as_str() {
+            "store" | "stored" | "none" => (Compression::Stored, None),
+            "deflate" => (Compression::Deflate(level), Some(0..=9)),
+            "bzip2" => (Compression::Bzip2(level), Some(1..=9)),
+            "zstd" => (Compression::Zstd(level), Some(1..=22)),
+            "xz" => (Compression::Xz(level), Some(0..=9)),
+            _ => return Err(format!("unknown compression method '{}' (expected store, deflate, bzip2, zstd or xz)", method)),
+        };
+
+        match (level, levels) {
+            (Some(_), None) => Err(format!("{} doesn't take a compression level", method)),
+            (Some(level), Some(levels)) if !levels.contains(&level) => Err(format!(
+                "{} compression level must be between {} and {}",
+                method,
+                levels.start(),
+                levels.end()
+            )),
+            _ => Ok(compression),
+        }
+    }
+}
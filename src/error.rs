@@ -0,0 +1,102 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use zip::result::ZipError;
+
+use crate::format::Format;
+
+/// Errors that stop an archiving run or the writing of an archive.
+///
+/// Problems with single files (e.g. a file that can't be opened) don't stop
+/// a run, and neither do failed archives; both are collected in the
+/// [`ArchiveReport`](crate::ArchiveReport).
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or folder failed.
+    Io { path: PathBuf, source: io::Error },
+    /// Writing a zip archive failed.
+    Zip { path: PathBuf, source: ZipError },
+    /// A selected file ending doesn't exist in the scanned folder.
+    UnknownExtension(String),
+    /// The file name doesn't end in the extension of a supported archive format.
+    UnknownFormat(PathBuf),
+    /// Reading or writing the catalog database failed.
+    Catalog { path: PathBuf, source: rusqlite::Error },
+    /// A file name pattern isn't valid.
+    InvalidPattern(String),
+    /// No restore target was given and the archive isn't in an `archive/` folder.
+    NoRestoreTarget(PathBuf),
+    /// The archive is encrypted and no password was given.
+    PasswordRequired(PathBuf),
+    /// The password doesn't open the encrypted archive.
+    WrongPassword(PathBuf),
+    /// Only zip archives can be encrypted.
+    EncryptionUnsupported(Format),
+    /// The archive is encrypted to public keys and no identity was given.
+    IdentityRequired(PathBuf),
+    /// None of the given identities belongs to a key the archive is encrypted to.
+    NoMatchingIdentity(PathBuf),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io { path: path.into(), source }
+    }
+
+    pub(crate) fn zip(path: impl Into<PathBuf>, source: ZipError) -> Self {
+        Error::Zip { path: path.into(), source }
+    }
+
+    pub(crate) fn catalog(path: impl Into<PathBuf>, source: rusqlite::Error) -> Self {
+        Error::Catalog { path: path.into(), source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Zip { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::UnknownExtension(extension) => write!(f, "no [*.{}] files in the folder", extension),
+            Error::Catalog { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::InvalidPattern(reason) => write!(f, "invalid pattern: {}", reason),
+            Error::UnknownFormat(path) => write!(f, "{}: not a zip or tar archive", path.display()),
+            Error::NoRestoreTarget(path) => write!(
+                f,
+                "{}: the archive is not in an archive folder, choose where to restore it",
+                path.display()
+            ),
+            Error::PasswordRequired(path) => write!(f, "{}: the archive is encrypted, a password is needed", path.display()),
+            Error::WrongPassword(path) => write!(f, "{}: wrong password", path.display()),
+            Error::EncryptionUnsupported(format) => write!(f, "{} archives can't be encrypted, only zip archives", format),
+            Error::IdentityRequired(path) => write!(
+                f,
+                "{}: the archive is encrypted to public keys, an identity file is needed",
+                path.display()
+            ),
+            Error::NoMatchingIdentity(path) => write!(f, "{}: the archive isn't encrypted to any of the identities", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Zip { source, .. } => Some(source),
+            Error::Catalog { source, .. } => Some(source),
+            Error::UnknownExtension(_)
+            | Error::InvalidPattern(_)
+            | Error::UnknownFormat(_)
+            | Error::NoRestoreTarget(_)
+            | Error::PasswordRequired(_)
+            | Error::WrongPassword(_)
+            | Error::EncryptionUnsupported(_)
+            | Error::IdentityRequired(_)
+            | Error::NoMatchingIdentity(_) => None,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+
+use crate::compression::Compression;
+
+/// Container format of the written archives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Zip,
+    TarGz,
+    TarZst,
+    TarXz,
+}
+
+impl Format {
+    /// File extension of the format, without the leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::TarGz => "tar.gz",
+            Format::TarZst => "tar.zst",
+            Format::TarXz => "tar.xz",
+        }
+    }
+
+    /// The format of an archive, going by its file name. Archives encrypted
+    /// to public keys (`name.zip.age`) have the format of the archive inside.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let file_name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        let file_name = file_name.strip_suffix(ENCRYPTED_SUFFIX).unwrap_or(&file_name);
+        [Format::Zip, Format::TarGz, Format::TarZst, Format::TarXz]
+            .into_iter()
+            .find(|format| file_name.ends_with(&format!(".{}", format.extension())))
+    }
+
+    pub fn is_tar(&self) -> bool {
+        *self != Format::Zip
+    }
+
+    /// The compression a tarball of this format is written with. The level of
+    /// `requested` is used if it is the same method, otherwise the method's
+    /// default level. Zip archives compress every entry on its own, so
+    /// `requested` is returned as is.
+    pub fn compression(&self, requested: Compression) -> Compression {
+        match (self, requested) {
+            (Format::Zip, _) => requested,
+            (Format::TarGz, Compression::Deflate(level)) => Compression::Deflate(level),
+            (Format::TarGz, _) => Compression::Deflate(None),
+            (Format::TarZst, Compression::Zstd(level)) => Compression::Zstd(level),
+            (Format::TarZst, _) => Compression::Zstd(None),
+            (Format::TarXz, Compression::Xz(level)) => Compression::Xz(level),
+            (Format::TarXz, _) => Compression::Xz(None),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().trim_start_matches('.').to_ascii_lowercase().as_str() {
+            "zip" => Ok(Format::Zip),
+            "tar.gz" | "tgz" => Ok(Format::TarGz),
+            "tar.zst" | "tzst" => Ok(Format::TarZst),
+            "tar.xz" | "txz" => Ok(Format::TarXz),
+            _ => Err(format!("unknown format '{}' (expected zip, tar.gz, tar.zst or tar.xz)", input)),
+        }
+    }
+}
+
+/// Ending of archives encrypted to public keys, e.g. `name.zip.age`, see
+/// [`recipient`](crate::recipient).
+pub const ENCRYPTED_SUFFIX: &str = ".age";
+
+/// Whether `path` names an archive encrypted to public keys.
+pub fn is_encrypted(path: &Path) -> bool {
+    Format::from_path(path).is_some() && path.to_string_lossy().to_ascii_lowercase().ends_with(ENCRYPTED_SUFFIX)
+}
+
+/// The file name of an archive without the format's extension (and the
+/// ending of encrypted archives), e.g. `csv_archive_2024_3_1_14_5`.
+pub(crate) fn archive_stem(path: &Path) -> Option<String> {
+    let format = Format::from_path(path)?;
+    let name = path.file_name()?.to_string_lossy().to_string();
+    let length = name.len() - format.extension().len() - 1 - if is_encrypted(path) { ENCRYPTED_SUFFIX.len() } else { 0 };
+    Some(name[..length].to_string())
+}
+
+/// Compressing writer underneath a tarball.
+pub(crate) enum TarEncoder<W: Write> {
+    Gz(GzEncoder<W>),
+    Zst(zstd::Encoder<'static, W>),
+    Xz(XzEncoder<W>),
+}
+
+impl<W: Write> TarEncoder<W> {
+    pub(crate) fn new(format: Format, compression: Compression, file: W) -> io::Result<Self> {
+        let level = compression.level();
+        match format {
+            Format::TarGz => {
+                let level = level.unwrap_or(6) as u32;
+                Ok(TarEncoder::Gz(GzEncoder::new(file, flate2::Compression::new(level))))
+            }
+            Format::TarZst => {
+                let level = level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL as i64) as i32;
+                Ok(TarEncoder::Zst(zstd::Encoder::new(file, level)?))
+            }
+            Format::TarXz => {
+                let level = level.unwrap_or(6) as u32;
+                Ok(TarEncoder::Xz(XzEncoder::new(file, level)))
+            }
+            Format::Zip => Err(io::Error::new(io::ErrorKind::InvalidInput, "zip is not a tar format")),
+        }
+    }
+
+    /// Writes the end of the compressed stream.
+    pub(crate) fn finish(self) -> io::Result<W> {
+        match self {
+            TarEncoder::Gz(encoder) => encoder.finish(),
+            TarEncoder::Zst(encoder) => encoder.finish(),
+            TarEncoder::Xz(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for TarEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            TarEncoder::Gz(encoder) => encoder.write(buf),
+            TarEncoder::Zst(encoder) => encoder.write(buf),
+            TarEncoder::Xz(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            TarEncoder::Gz(encoder) => encoder.flush(),
+            TarEncoder::Zst(encoder) => encoder.flush(),
+            TarEncoder::Xz(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Opens the decompressed stream of a tarball.
+pub(crate) fn tar_reader<'a>(format: Format, file: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+    match format {
+        Format::TarGz => Ok(Box::new(GzDecoder::new(file))),
+        Format::TarZst => Ok(Box::new(zstd::Decoder::new(file)?)),
+        Format::TarXz => Ok(Box::new(XzDecoder::new(file))),
+        Format::Zip => Err(io::Error::new(io::ErrorKind::InvalidInput, "zip is not a tar format")),
+    }
+}
@@ -0,0 +1,63 @@
+//! Library behind the archivist command line tool.
+//!
+//! Archiving is done in three steps: a [`Scanner`] collects the files of a
+//! folder, an [`ArchivePlan`] decides which files go into which archive and
+//! an [`Archiver`] writes the archives and returns an [`ArchiveReport`].
+//!
+//! ```no_run
+//! use the_archivist::{ArchivePlan, Archiver, Scanner};
+//!
+//! let scan = Scanner::new("/data/exports").scan()?;
+//! let plan = ArchivePlan::per_type(&scan);
+//! let report = Archiver::new().execute(&plan)?;
+//! for archive in report.written() {
+//!     println!("{} ({} files)", archive.path.display(), archive.files.len());
+//! }
+//! # Ok::<(), the_archivist::Error>(())
+//! ```
+
+pub mod age;
+pub mod archiver;
+pub mod binary;
+pub mod catalog;
+pub mod cleanup;
+pub mod compression;
+pub mod error;
+pub mod format;
+pub mod manifest;
+pub mod matcher;
+pub mod naming;
+pub mod password;
+pub mod plan;
+pub mod policy;
+pub mod recipient;
+pub mod report;
+pub mod restore;
+pub mod retention;
+pub mod scanner;
+pub mod trash;
+pub mod verify;
+pub mod volume;
+
+pub use age::{AgeFilter, TimeSpec, Timestamp};
+pub use archiver::Archiver;
+pub use binary::{BinaryAction, BinaryClass, BinaryPolicy};
+pub use catalog::{Catalog, CatalogEntry, Query};
+pub use compression::Compression;
+pub use error::{Error, Result};
+pub use format::Format;
+pub use manifest::{read_manifest, read_manifest_decrypt, Manifest, ManifestEntry};
+pub use matcher::NameMatcher;
+pub use naming::NameTemplate;
+pub use password::Password;
+pub use plan::{ArchivePlan, PlannedArchive, Selection};
+pub use policy::CompressionPolicy;
+pub use recipient::{Identity, Recipient};
+pub use report::{ArchiveReport, ArchiveResult};
+pub use restore::{Conflict, RestoreReport, Restorer};
+pub use retention::{ArchiveInfo, KeepReason, PrunePlan, Retention};
+pub use scanner::{FileEntry, ScanResult, Scanner, SkipReason, Skipped};
+pub use verify::{verify_archive, Verification};
+
+/// Version of the tool, as shown in the banner.
+pub const VERSION: &str = "1.1.0";
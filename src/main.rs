@@ -1,670 +1,1154 @@
-
-
-use std::fs;
-use std::path::Path;
 use std::io::{self, Write};
-use zip::{ZipWriter, write::FileOptions, CompressionMethod};
-use chrono::Local;
-use chrono::Datelike;
-use chrono::Timelike;
-
+use std::path::{Path, PathBuf};
 
+use chrono::Local;
+use clap::{Parser, ValueEnum};
+use the_archivist::catalog;
+use the_archivist::cleanup::{self, Outcome};
+use the_archivist::restore::RestoreOutcome;
+use the_archivist::scanner::ARCHIVE_FOLDER;
+use the_archivist::{format, recipient};
+use the_archivist::{
+    AgeFilter, ArchivePlan, ArchiveResult, Archiver, BinaryPolicy, Catalog, Compression,
+    CompressionPolicy, Error, Format, Identity, NameMatcher, NameTemplate, PrunePlan, Query,
+    Recipient, Restorer, Retention, ScanResult, Scanner,
+};
+
+mod cli;
+mod profile;
+mod secret;
+mod watch;
+
+use cli::{
+    AfterArchive, Cli, Command, FindArgs, ImportArgs, KeygenArgs, Mode, OutputArgs, PruneArgs,
+    RestoreArgs, RunArgs, ValidateArgs, WatchArgs,
+};
+use profile::{ConfigFile, Profile};
+use watch::WatchOptions;
+
+/// Everything a run needs to know. Values left as `None` are asked for
+/// interactively when they are needed.
+struct Settings {
+    folder: Option<String>,
+    recursive: Option<bool>,
+    mode: Option<Mode>,
+    extensions: Option<Vec<String>>,
+    name: Option<NameMatcher>,
+    after_archive: Option<AfterArchive>,
+    confirm_delete: Option<bool>,
+    compression: Compression,
+    policy: CompressionPolicy,
+    format: Format,
+    /// Split the archives into volumes of this size.
+    volume_size: Option<u64>,
+    /// Encrypt the archives, with the password read from this file
+    /// descriptor or else the environment or a prompt.
+    encrypt: bool,
+    password_fd: Option<i32>,
+    /// Encrypt the archives to these public keys.
+    recipients: Vec<Recipient>,
+    /// Number of archives written at the same time, one per CPU core if
+    /// not given.
+    jobs: Option<usize>,
+    /// Only archive files of this age and size; not asked for interactively.
+    age: Option<AgeFilter>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    gitignore: bool,
+    binaries: BinaryPolicy,
+    /// Where the archives go and how they are named, if not the defaults.
+    destination: Option<PathBuf>,
+    name_template: Option<NameTemplate>,
+    /// Prune the archive folder after the run.
+    retention: Option<Retention>,
+    dry_run: bool,
+    interactive: bool,
+}
 
 fn main() {
+    let cli = Cli::parse();
+
+    let settings = match cli.command {
+        Some(Command::Archive(args)) => Settings {
+            age: args.age_filter(),
+            min_size: args.min_size,
+            max_size: args.max_size,
+            gitignore: args.gitignore,
+            binaries: args
+                .binaries
+                .iter()
+                .fold(BinaryPolicy::new(), |policy, (class, action)| policy.set(*class, *action)),
+            folder: Some(args.folder.to_string_lossy().to_string()),
+            recursive: Some(args.recursive),
+            mode: Some(args.mode),
+            extensions: Some(args.ext).filter(|ext| !ext.is_empty()),
+            name: if args.name.is_empty() {
+                None
+            } else {
+                match NameMatcher::new(&args.name, &args.exclude_name, args.ignore_case) {
+                    Ok(matcher) => Some(matcher),
+                    Err(err) => {
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            },
+            after_archive: Some(if args.trash {
+                AfterArchive::Trash
+            } else if args.delete {
+                AfterArchive::Delete
+            } else {
+                AfterArchive::Keep
+            }),
+            confirm_delete: if args.yes { Some(true) } else { None },
+            compression: args.output.compression.unwrap_or_default(),
+            policy: args.output.policy(),
+            format: args.output.format.unwrap_or_default(),
+            volume_size: args.output.volume_size,
+            encrypt: args.output.encrypt,
+            password_fd: args.output.password_fd,
+            recipients: cli_recipients(&args.output),
+            jobs: args.output.jobs,
+            destination: args.destination,
+            name_template: args.name_template,
+            retention: args.retention.retention(),
+            dry_run: args.dry_run,
+            interactive: false,
+        },
+        Some(Command::Restore(args)) => {
+            restore(args);
+            return;
+        }
+        Some(Command::Find(args)) => {
+            find(args);
+            return;
+        }
+        Some(Command::Import(args)) => {
+            import(args);
+            return;
+        }
+        //options given on the command line win over the profile
+        Some(Command::Run(args)) => profile_settings(args),
+        Some(Command::Validate(args)) => {
+            validate(args);
+            return;
+        }
+        Some(Command::Watch(args)) => {
+            let options = WatchOptions {
+                compression: args.output.compression,
+                format: args.output.format,
+                volume_size: args.output.volume_size,
+                encrypt: args.output.encrypt,
+                password: None,
+                recipients: cli_recipients(&args.output),
+                jobs: args.output.jobs,
+                policy: args.output.policy(),
+            };
+            watch(args, options);
+            return;
+        }
+        Some(Command::Prune(args)) => {
+            prune(args);
+            return;
+        }
+        Some(Command::Keygen(args)) => {
+            keygen(args);
+            return;
+        }
+        None => Settings {
+            folder: None,
+            recursive: None,
+            mode: None,
+            extensions: None,
+            name: None,
+            after_archive: None,
+            confirm_delete: None,
+            compression: cli.output.compression.unwrap_or_default(),
+            policy: cli.output.policy(),
+            format: cli.output.format.unwrap_or_default(),
+            volume_size: cli.output.volume_size,
+            encrypt: cli.output.encrypt,
+            password_fd: cli.output.password_fd,
+            recipients: cli_recipients(&cli.output),
+            jobs: cli.output.jobs,
+            age: None,
+            min_size: None,
+            max_size: None,
+            gitignore: false,
+            binaries: BinaryPolicy::new(),
+            destination: None,
+            name_template: None,
+            retention: None,
+            dry_run: false,
+            interactive: true,
+        },
+    };
+
+    run(settings);
+}
 
-    colored::control::set_virtual_terminal(true).unwrap();
+/// The public keys of --recipient and --recipients-file, exiting if the
+/// file can't be read.
+fn cli_recipients(output: &OutputArgs) -> Vec<Recipient> {
+    let mut recipients = output.recipient.clone();
+    if let Some(file) = &output.recipients_file {
+        match recipient::read_recipients(file) {
+            Ok(file_recipients) => recipients.extend(file_recipients),
+            Err(err) => {
+                eprintln!("\x1b[31mError: {}\x1b[37m", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    recipients
+}
 
-    //give the console print a color blue
-    println!("\x1b[34m");
-    //
-    println!(r" _____ _               _             _     _       _     _     _ ");
-    println!(r"|_   _| |__   ___     / \   _ __ ___| |__ (_)_   _(_)___| |_  | |");
-    println!(r"  | | | '_ \ / _ \   / _ \ | '__/ __| '_ \| \ \ / / / __| __| | |");
-    println!(r"  | | | | | |  __/  / ___ \| | | (__| | | | |\ V /| \__ \ |_  |_|");
-    println!(r"  |_| |_| |_|\___| /_/   \_\_|  \___|_| |_|_| \_/ |_|___/\__| (_)");
+/// Reads one trimmed line from stdin.
+fn read_input() -> String {
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
 
-    //give the console print a color gray
-    println!("\x1b[90m");
+/// Asks a y/n question until the answer is valid.
+fn ask_yes_no(question: &str) -> bool {
+    println!("{} (y/n)", question);
+    let mut answer = read_input();
 
-    println!("\nBuild: 1.1.0");
-    //println!("\n2024\n");
-    println!("This tool will archive files in a folder and optionally delete them after archiving");
-    println!("Folders can be archived entirely, by file type or by identifier string\n");
-    println!("For more information visit: https://github.com/tkuerschner/the_archivist");
+    //let the user repeat if the input was wrong
+    while answer != "y" && answer != "n" {
+        println!("-----------------------------------------------");
+        println!("Invalid option, please try again");
+        println!("-----------------------------------------------");
+        println!("{} (y/n)", question);
+        answer = read_input();
+    }
 
-    //give the console print a color white
-    println!("\x1b[37m");
-    print!("\nPlease enter the folder location: ");
-    io::stdout().flush().unwrap();
-    let mut folder_location = String::new();
-    std::io::stdin().read_line(&mut folder_location).unwrap();
-    folder_location = folder_location.trim().to_string();
+    answer == "y"
+}
 
-   
+/// Shows the archiving options and asks for one until the choice is valid.
+fn ask_mode() -> Mode {
     loop {
-        match fs::read_dir(&folder_location) {
-            Ok(_files) => {
-                break;
-            }
-            Err(err) => {
-                eprintln!("Error reading directory: {}", err);
-                print!("Please re-enter the folder location: ");
-                io::stdout().flush().unwrap();
-                folder_location.clear();
-                std::io::stdin().read_line(&mut folder_location).unwrap();
-                folder_location = folder_location.trim().to_string();
-            }
-        }
-    }
+        println!("Choose an option: ");
+        println!("1 - All files in one archive");
+        println!("2 - Separate archives for each file type");
+        println!("3 - Select specific file types for archiving (one archive per selected file type)");
+        println!("4 - All files containing a specific string in the name");
 
-    // count the files in the folder excluding subfolders
-    let mut file_count = 0;
-    for entry in fs::read_dir(&folder_location).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        if path.is_file() {
-            file_count += 1;
+        match read_input().as_str() {
+            "1" => return Mode::All,
+            "2" => return Mode::PerType,
+            "3" => return Mode::ByExtension,
+            "4" => return Mode::ByName,
+            //let the user repeat if the input was wrong
+            _ => {
+                println!("-----------------------------------------------");
+                println!("Invalid option, please try again");
+                println!("-----------------------------------------------");
+            }
         }
     }
+}
 
+/// Asks for a comma separated list of file endings.
+fn ask_file_endings() -> Vec<String> {
     println!("-----------------------------------------------");
-    println!("Number of files in the folder [{}]", file_count);
-    println!("-----------------------------------------------\n");
+    println!("Please enter the file endings separated by comma: ");
+    read_input().split(',').map(|s| s.trim().to_string()).collect()
+}
 
-    // Get all the different file endings in the folder
-    let mut file_endings = Vec::new();
-    for entry in fs::read_dir(&folder_location).unwrap_or_else(|err| {
-        eprintln!("Error reading directory: {}", err);
-        std::process::exit(1);
-    }) {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        if let Some(extension) = path.extension() {
-            let file_ending = extension.to_str().unwrap().to_owned();
-            if !file_endings.contains(&file_ending) {
-                file_endings.push(file_ending);
+/// Decides what happens to the archived files, asking the user for whatever
+/// was not given on the command line.
+fn after_archive(settings: &Settings) -> AfterArchive {
+    //ask the user if they want to remove the files after archiving
+    let after_archive = match settings.after_archive {
+        Some(after_archive) => after_archive,
+        None if !ask_yes_no("Do you want to remove the files after archiving?") => AfterArchive::Keep,
+        None => loop {
+            println!("Move the files to the trash (t) or delete them permanently (d)? (t/d)");
+            match read_input().as_str() {
+                "t" => break AfterArchive::Trash,
+                "d" => break AfterArchive::Delete,
+                //let the user repeat if the input was wrong
+                _ => {
+                    println!("-----------------------------------------------");
+                    println!("Invalid option, please try again");
+                    println!("-----------------------------------------------");
+                }
             }
+        },
+    };
+
+    if after_archive != AfterArchive::Delete {
+        return after_archive;
+    }
+
+    let confirmed = match settings.confirm_delete {
+        Some(confirmed) => confirmed,
+        None => {
+            println!("\x1b[31m Warning - This action is irreversible!\x1b[37m");
+            println!("-----------------------------------------------");
+            ask_yes_no("Are you sure you want to delete the files?")
         }
+    };
+    if confirmed {
+        AfterArchive::Delete
+    } else {
+        AfterArchive::Keep
     }
+}
 
-    //ignore subfolders
-    file_endings.retain(|x| x != "");
+/// Formats a size in bytes for humans, e.g. `1.5 MB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
 
+/// Prints what a run would do, without touching anything.
+fn print_plan(scan: &ScanResult, plan: &ArchivePlan, settings: &Settings) {
+    println!("-----------------------------------------------");
+    println!("\x1b[33mDry run - no archives are written and no files are removed\x1b[37m");
+    println!("-----------------------------------------------");
 
-    // create list of files that should always be ignored
-    // ignore the executable file
-    let mut ignore_files = Vec::new();
-    ignore_files.push("the_archivist.exe".to_string());
-    //ignore everything that starts with a dot
-    for entry in fs::read_dir(&folder_location).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        if let Some(file_name) = path.file_name() {
-            if file_name.to_str().unwrap().starts_with(".") {
-                ignore_files.push(file_name.to_str().unwrap().to_string());
-            }
+    for archive in &plan.archives {
+        let total: u64 = archive.files.iter().map(|file| file.size).sum();
+        let mut file_name = archive.file_name(settings.format);
+        if !settings.recipients.is_empty() {
+            file_name.push_str(format::ENCRYPTED_SUFFIX);
         }
+        println!("Archive: \x1b[96m{}\x1b[37m", plan.archive_folder.join(file_name).display());
+        for file in &archive.files {
+            println!("    {} ({})", file.name, format_size(file.size));
+        }
+        println!("Files [{}], total size [{}]", archive.files.len(), format_size(total));
+        println!("-----------------------------------------------");
     }
-  
 
-    let archive_folder = Path::new(&folder_location).join("archive");
-        if let Err(err) = fs::create_dir_all(&archive_folder) {
-            eprintln!("Error creating archive directory: {}", err);
-            std::process::exit(1);
+    let unselected = plan.unselected(scan);
+    if !scan.skipped.is_empty() || !unselected.is_empty() {
+        println!("Skipped files:");
+        for skipped in &scan.skipped {
+            println!("    {} ({})", skipped.name, skipped.reason);
         }
-
-    //if there is an exe in the file endings, remove it
-    if let Some(index) = file_endings.iter().position(|x| x == "exe") {
-        file_endings.remove(index);
-        println!("Binary files detected, excluding them from archiving");
-        println!("-----------------------------------------------\n");
+        for file in unselected {
+            println!("    {} (not selected)", file.name);
+        }
+        println!("-----------------------------------------------");
     }
 
-    println!("Filetypes detected:\x1b[96m {:?}", file_endings);
-    println!("\x1b[37m");
-    println!("-----------------------------------------------\n");
+    let action = match settings.after_archive {
+        Some(AfterArchive::Trash) => "moved to the trash",
+        Some(AfterArchive::Delete) => "deleted",
+        _ => return,
+    };
+    println!("Files that would be {} after they pass verification:", action);
+    for file in plan.files() {
+        println!("    {}", file.path.display());
+    }
+    println!("-----------------------------------------------");
+}
 
-    //display the amount of files for each file type
+/// Prints what happened to every archived file after archiving.
+fn print_cleanup_summary(results: &[cleanup::CleanupResult]) {
+    let (mut deleted, mut trashed, mut kept, mut failed) = (0, 0, 0, 0);
 
-    for file_ending in &file_endings {
-        let mut file_count = 0;
-        for entry in fs::read_dir(&folder_location).unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if let Some(extension) = path.extension() {
-                if extension.to_str().unwrap() == file_ending {
-                    file_count += 1;
-                }
+    println!("-----------------------------------------------");
+    for result in results {
+        match &result.outcome {
+            Outcome::Deleted => {
+                deleted += 1;
+                println!("Deleted: {}", result.path.display());
+            }
+            Outcome::Trashed(_) => {
+                trashed += 1;
+                println!("Moved to trash: {}", result.path.display());
+            }
+            Outcome::Kept(reason) => {
+                kept += 1;
+                println!("\x1b[33mKept: {} ({})\x1b[37m", result.path.display(), reason);
+            }
+            Outcome::Failed(err) => {
+                failed += 1;
+                eprintln!("\x1b[31mError removing file {}: {}\x1b[37m", result.path.display(), err);
             }
         }
-        println!("Number of\x1b[96m [*.{}]\x1b[37m files in the folder [{}]\n", file_ending, file_count);
     }
+    println!("-----------------------------------------------");
+    println!(
+        "Files deleted [{}], moved to trash [{}], kept [{}], failed [{}]",
+        deleted, trashed, kept, failed
+    );
+    println!("-----------------------------------------------");
+}
 
-    println!("Choose an option: ");
-    println!("1 - All files in one archive");
-    println!("2 - Separate archives for each file type");
-    println!("3 - Select specific file types for archiving (one archive per selected file type)");
-    println!("4 - All files containing a specific string in the name");
-
-    let mut option = String::new();
-    std::io::stdin().read_line(&mut option).unwrap();
-    option = option.trim().to_string();
-
-    //let the user repeat if the input was wrong
-    while option != "1" && option != "2" && option != "3" && option != "4" {
-        println!("-----------------------------------------------");
-        println!("Invalid option, please try again");
-        println!("-----------------------------------------------");
-        println!("Choose an option: ");
-        println!("1 - All files in one archive");
-        println!("2 - Separate archives for each file type");
-        println!("3 - Select specific file types for archiving (one archive per selected file type)");
-        option.clear();
-        std::io::stdin().read_line(&mut option).unwrap();
-        option = option.trim().to_string();
-    }
-
-    if option == "1" {
-
-    
-        let now = Local::now();
-        let zip_name = format!(
-            "full_archive_{}_{}_{}_{}_{}.zip",
-            now.year(),
-            now.month(),
-            now.day(),
-            now.hour(),
-            now.minute()
-        );
-        let zip_path = archive_folder.join(&zip_name);
-        let zip_file = match fs::File::create(&zip_path) {
-            Ok(file) => file,
+/// Extracts an archive and prints what happened to every entry.
+fn restore(args: RestoreArgs) {
+    let mut restorer = Restorer::new().conflict(args.conflict).special_bits(args.special_bits);
+    if let Some(target) = &args.target {
+        restorer = restorer.target(target);
+    }
+    let mut identities = Vec::new();
+    for file in &args.identity {
+        match recipient::read_identities(file) {
+            Ok(file_identities) => identities.extend(file_identities),
             Err(err) => {
-                eprintln!("Error creating zip file {}: {}", zip_path.display(), err);
+                eprintln!("\x1b[31mError: {}\x1b[37m", err);
                 std::process::exit(1);
             }
-        };
-        let mut zip = ZipWriter::new(zip_file);
-        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
-
-        for entry in fs::read_dir(&folder_location).unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            let file_name = path.file_name().unwrap().to_str().unwrap();
-
-            //ignore subfolders
-            if path.is_dir() {
-                continue;
+        }
+    }
+    restorer = restorer.identities(identities);
+
+    //the password is only asked for once it turns out to be needed
+    let mut result = restorer.restore(&args.archive);
+    if let Err(Error::PasswordRequired(_)) = result {
+        match secret::password(args.password_fd, false) {
+            Ok(password) => {
+                restorer = restorer.password(password);
+                result = restorer.restore(&args.archive);
             }
-
-            if ignore_files.contains(&file_name.to_string()) {
-                continue;
+            Err(err) => {
+                eprintln!("\x1b[31mError: {}\x1b[37m", err);
+                std::process::exit(1);
             }
+        }
+    }
+    let report = match result {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("\x1b[31mError restoring archive: {}\x1b[37m", err);
+            std::process::exit(1);
+        }
+    };
 
-            if let Err(err) = zip.start_file(file_name, options) {
-                eprintln!("Error starting file in zip {}: {}", file_name, err);
-                continue;
+    let (mut restored, mut skipped, mut failed) = (0, 0, 0);
+    println!("-----------------------------------------------");
+    println!("Restoring {} to {}", args.archive.display(), report.target.display());
+    println!("-----------------------------------------------");
+    for entry in &report.entries {
+        match &entry.outcome {
+            RestoreOutcome::Restored(_) => {
+                restored += 1;
+                println!("Restored: {}", entry.name);
+            }
+            RestoreOutcome::Overwritten(_) => {
+                restored += 1;
+                println!("Overwritten: {}", entry.name);
+            }
+            RestoreOutcome::Renamed(path) => {
+                restored += 1;
+                println!("Restored as: {}", path.display());
             }
+            RestoreOutcome::Skipped(reason) => {
+                skipped += 1;
+                println!("\x1b[33mSkipped: {} ({})\x1b[37m", entry.name, reason);
+            }
+            RestoreOutcome::Failed(reason) => {
+                failed += 1;
+                eprintln!("\x1b[31mError restoring {}: {}\x1b[37m", entry.name, reason);
+            }
+        }
+    }
+    println!("-----------------------------------------------");
+    println!("Entries restored [{}], skipped [{}], failed [{}]", restored, skipped, failed);
+    println!("-----------------------------------------------");
 
-            let mut file = match fs::File::open(&path) {
-                Ok(file) => file,
-                Err(_err) => {
-                    //  eprintln!("Error opening file {}: {}", path.display(), err);
-                    continue;
-                }
-            };
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
 
-            if let Err(err) = io::copy(&mut file, &mut zip) {
-                eprintln!("Error copying file {} to zip: {}", path.display(), err);
-            }
+/// The archive folder of `folder`, or `folder` itself if it is one.
+fn archive_folder(folder: &Path) -> PathBuf {
+    let archive_folder = folder.join(ARCHIVE_FOLDER);
+    if archive_folder.is_dir() {
+        archive_folder
+    } else {
+        folder.to_path_buf()
+    }
+}
+
+/// Searches the catalog and prints which archives hold the matching files.
+fn find(args: FindArgs) {
+    let archive_folder = archive_folder(&args.folder);
+    if !archive_folder.join(catalog::CATALOG_NAME).is_file() {
+        eprintln!(
+            "\x1b[31mNo catalog in {}, use the import command to index existing archives\x1b[37m",
+            archive_folder.display()
+        );
+        std::process::exit(1);
+    }
+
+    let mut query = Query::new();
+    if let Some(pattern) = &args.pattern {
+        query = query.name(pattern);
+    }
+    if let Some(size) = args.min_size {
+        query = query.min_size(size);
+    }
+    if let Some(size) = args.max_size {
+        query = query.max_size(size);
+    }
+    if let Some(date) = args.after {
+        query = query.archived_after(date);
+    }
+    if let Some(date) = args.before {
+        query = query.archived_before(date);
+    }
+
+    let found = match Catalog::open(&archive_folder).and_then(|catalog| catalog.find(&query)) {
+        Ok(found) => found,
+        Err(err) => {
+            eprintln!("\x1b[31mError searching the catalog: {}\x1b[37m", err);
+            std::process::exit(1);
         }
+    };
 
-        if let Err(err) = zip.finish() {
-            eprintln!("Error finishing zip file {}: {}", zip_path.display(), err);
+    println!("-----------------------------------------------");
+    for entry in &found {
+        println!(
+            "{} ({}) in {}, archived {}",
+            entry.name,
+            format_size(entry.size),
+            entry.archive.file_name().unwrap_or_default().to_string_lossy(),
+            entry.archived.format("%Y-%m-%d %H:%M")
+        );
+    }
+    println!("-----------------------------------------------");
+    println!("Files found: [{}]", found.len());
+    println!("-----------------------------------------------");
+}
+
+/// Adds the archives of a folder to its catalog.
+fn import(args: ImportArgs) {
+    let archive_folder = archive_folder(&args.folder);
+    let result = catalog::archives_in(&archive_folder).and_then(|archives| {
+        let mut catalog = Catalog::open(&archive_folder)?;
+        let mut imported = 0;
+        for archive in &archives {
+            match catalog.import(archive) {
+                Ok(true) => {
+                    imported += 1;
+                    println!("Imported: {}", archive.display());
+                }
+                Ok(false) => println!("Already in the catalog: {}", archive.display()),
+                Err(err) => eprintln!("\x1b[31mError importing {}\x1b[37m", err),
+            }
         }
-        println!("-----------------------------------------------");
-        println!("Archive created: \x1b[96m{}\x1b[37m", zip_path.display());
-        println!("-----------------------------------------------");
-        //ask the user if they want to delete the files after archiving
-        println!("Do you want to delete the files after archiving? (y/n)");
-        let mut delete_option = String::new();
-        std::io::stdin().read_line(&mut delete_option).unwrap();
-        delete_option = delete_option.trim().to_string();
-
-        //let the user repeat if the input was wrong
-        while delete_option != "y" && delete_option != "n" {
+        Ok((imported, archives.len()))
+    });
+
+    match result {
+        Ok((imported, total)) => {
             println!("-----------------------------------------------");
-            println!("Invalid option, please try again");
+            println!("Archives imported: [{}/{}]", imported, total);
             println!("-----------------------------------------------");
-            println!("Do you want to delete the files after archiving? (y/n)");
-            delete_option.clear();
-            std::io::stdin().read_line(&mut delete_option).unwrap();
-            delete_option = delete_option.trim().to_string();
         }
+        Err(err) => {
+            eprintln!("\x1b[31mError importing archives: {}\x1b[37m", err);
+            std::process::exit(1);
+        }
+    }
+}
 
-        if delete_option == "y" {
+/// The config files to read, the given one or the default ones.
+fn config_files(config: Option<PathBuf>) -> Vec<PathBuf> {
+    match config {
+        Some(config) => vec![config],
+        None => profile::config_files(),
+    }
+}
 
-            println!("\x1b[31m Warning - This action is irreversible!\x1b[37m");
-            println!("-----------------------------------------------");
-            println!("Are you sure you want to delete the files? (y/n)");
-            let mut delete_confirmation = String::new();
-            std::io::stdin().read_line(&mut delete_confirmation).unwrap();
-            delete_confirmation = delete_confirmation.trim().to_string();
-            
-              //let the user repeat if the input was wrong
-             while delete_option != "y" && delete_option != "n" {
-            println!("-----------------------------------------------");
-            println!("Invalid option, please try again");
-            println!("-----------------------------------------------");
-            println!("Do you want to delete the files after archiving? (y/n)");
-            delete_option.clear();
-            std::io::stdin().read_line(&mut delete_option).unwrap();
-            delete_option = delete_option.trim().to_string();
-        }
-
-            if delete_confirmation == "y" {
-                for entry in fs::read_dir(&folder_location).unwrap() {
-                    let entry = entry.unwrap();
-                    let path = entry.path();
-                    if let Err(err) = fs::remove_file(&path) {
-                        eprintln!("Error deleting file {}: {}", path.display(), err);
-                    }
-                }
+/// Looks up a profile, exiting with the mistakes in the config files if it
+/// isn't found or isn't valid. Returns the profile and its config file.
+fn load_profile(files: &[ConfigFile], name: &str) -> (Profile, PathBuf) {
+    //the first config file defining the profile wins
+    let Some(file) = files.iter().find(|file| file.defines(name)) else {
+        for file in files {
+            for err in file.errors.iter().filter(|err| err.profile.is_none()) {
+                eprintln!("\x1b[31m{}\x1b[37m", err);
             }
         }
+        eprintln!("\x1b[31mNo profile named '{}' found\x1b[37m", name);
+        std::process::exit(1);
+    };
+    let Some(profile) = file.profile(name).cloned() else {
+        for err in file.errors.iter().filter(|err| err.profile.as_deref() == Some(name)) {
+            eprintln!("\x1b[31m{}\x1b[37m", err);
+        }
+        std::process::exit(1);
+    };
+    (profile, file.path.clone())
+}
+
+/// The settings of a profile, with the options of the command line on top.
+fn profile_settings(args: RunArgs) -> Settings {
+    let cli = &args.output;
+    let files: Vec<ConfigFile> = config_files(args.config).iter().map(|path| ConfigFile::load(path)).collect();
+    let (profile, path) = load_profile(&files, &args.profile);
+
+    println!("-----------------------------------------------");
+    println!("Running profile \x1b[96m{}\x1b[37m from {}", profile.name, path.display());
+
+    Settings {
+        folder: Some(profile.folder.to_string_lossy().to_string()),
+        recursive: Some(profile.recursive),
+        mode: Some(profile.mode),
+        extensions: Some(profile.extensions).filter(|ext| !ext.is_empty()),
+        name: profile.matcher,
+        after_archive: Some(profile.after_archive),
+        confirm_delete: if args.yes { Some(true) } else { None },
+        compression: cli.compression.or(profile.compression).unwrap_or_default(),
+        policy: cli.policy(),
+        format: cli.format.or(profile.format).unwrap_or_default(),
+        volume_size: cli.volume_size.or(profile.volume_size),
+        encrypt: cli.encrypt || profile.encrypt,
+        password_fd: cli.password_fd,
+        recipients: Some(cli_recipients(cli)).filter(|recipients| !recipients.is_empty()).unwrap_or(profile.recipients),
+        jobs: cli.jobs.or(profile.jobs),
+        age: profile.age,
+        min_size: profile.min_size,
+        max_size: profile.max_size,
+        gitignore: profile.gitignore,
+        binaries: profile.binaries,
+        destination: profile.destination,
+        name_template: profile.name_template,
+        retention: profile.retention,
+        dry_run: args.dry_run,
+        interactive: false,
     }
+}
 
-    if option == "2"{
-        // create a zip file for each file type naming convention is filetype_archive_year_month_day_hour_minute.zip
-        for file_ending in &file_endings {
-            let now = Local::now();
-            let zip_name = format!(
-                "{}_archive_{}_{}_{}_{}_{}.zip",
-                file_ending,
-                now.year(),
-                now.month(),
-                now.day(),
-                now.hour(),
-                now.minute()
-            );
-            let zip_path = archive_folder.join(&zip_name);
-            let zip_file = match fs::File::create(&zip_path) {
-                Ok(file) => file,
-                Err(err) => {
-                    eprintln!("Error creating zip file {}: {}", zip_path.display(), err);
-                    std::process::exit(1);
-                }
-            };
-            let mut zip = ZipWriter::new(zip_file);
-            let options = FileOptions::default().compression_method(CompressionMethod::Stored);
-
-            for entry in fs::read_dir(&folder_location).unwrap() {
-                let entry = entry.unwrap();
-                let path = entry.path();
-                if let Some(extension) = path.extension() {
-                    if extension.to_str().unwrap() == file_ending {
-                        let file_name = path.file_name().unwrap().to_str().unwrap();
-
-                        if ignore_files.contains(&file_name.to_string()) {
-                            continue;
-                        }                      
-                        if let Err(err) = zip.start_file(file_name, options) {
-                            eprintln!("Error starting file in zip {}: {}", file_name, err);
-                            continue;
-                        }
-                        let mut file = match fs::File::open(&path) {
-                            Ok(file) => file,
-                            Err(err) => {
-                                eprintln!("Error opening file {}: {}", path.display(), err);
-                                continue;
-                            }
-                        };
-                        if let Err(err) = io::copy(&mut file, &mut zip) {
-                            eprintln!("Error copying file {} to zip: {}", path.display(), err);
-                        }
-                     
-                    }
-                }
-            }
+/// Watches the folders of profiles until the process is stopped.
+fn watch(args: WatchArgs, mut options: WatchOptions) {
+    let files: Vec<ConfigFile> = config_files(args.config).iter().map(|path| ConfigFile::load(path)).collect();
+    let profiles: Vec<Profile> = args.profiles.iter().map(|name| load_profile(&files, name).0).collect();
 
-            if let Err(err) = zip.finish() {
-                eprintln!("Error finishing zip file {}: {}", zip_path.display(), err);
+    //nobody is there to confirm, so deleting has to be allowed up front
+    if let Some(profile) = profiles.iter().find(|profile| profile.after_archive == AfterArchive::Delete && !args.yes) {
+        eprintln!(
+            "\x1b[31mProfile '{}' deletes the archived files permanently, pass --yes to allow it\x1b[37m",
+            profile.name
+        );
+        std::process::exit(1);
+    }
+
+    //the password is asked for once, before watching starts
+    let encrypted: Vec<&Profile> = profiles.iter().filter(|profile| options.encrypt || profile.encrypt).collect();
+    if let Some(profile) = encrypted.iter().find(|profile| options.format.or(profile.format).unwrap_or_default().is_tar()) {
+        let format = options.format.or(profile.format).unwrap_or_default();
+        eprintln!("\x1b[31mProfile '{}': {}\x1b[37m", profile.name, Error::EncryptionUnsupported(format));
+        std::process::exit(1);
+    }
+    if !encrypted.is_empty() {
+        match secret::password(args.output.password_fd, true) {
+            Ok(password) => options.password = Some(password),
+            Err(err) => {
+                eprintln!("\x1b[31mError: {}\x1b[37m", err);
+                std::process::exit(1);
             }
+        }
+    }
 
-            println!("-----------------------------------------------");
-            println!("Archive created: \x1b[96m{}\x1b[37m", zip_path.display());
-            println!("-----------------------------------------------");
+    if let Err(err) = watch::watch(profiles, &options) {
+        eprintln!("\x1b[31mError: {}\x1b[37m", err);
+        std::process::exit(1);
+    }
+}
+
+/// Checks the config files, printing every mistake with its line and the
+/// valid profiles.
+fn validate(args: ValidateArgs) {
+    let paths = config_files(args.config);
+    if paths.is_empty() {
+        println!("No config files found");
+        return;
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        let file = ConfigFile::load(path);
+        println!("-----------------------------------------------");
+        println!("{}", file.path.display());
+        for profile in &file.profiles {
+            let mode = profile.mode.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+            println!("    \x1b[96m{}\x1b[37m ({}, {})", profile.name, profile.folder.display(), mode);
+        }
+        for err in &file.errors {
+            failed = true;
+            eprintln!("\x1b[31m{}\x1b[37m", err);
         }
+        println!("Valid profiles [{}], errors [{}]", file.profiles.len(), file.errors.len());
+    }
+    println!("-----------------------------------------------");
 
-           
+    if failed {
+        std::process::exit(1);
+    }
+}
 
-          //ask the user if they want to delete the files after archiving
-          println!("Do you want to delete the files after archiving? (y/n)");
-          let mut delete_option = String::new();
-          std::io::stdin().read_line(&mut delete_option).unwrap();
-          delete_option = delete_option.trim().to_string();
+/// Prints which archives a prune keeps, with the rules keeping them, and
+/// which it removes.
+fn print_prune_plan(plan: &PrunePlan) {
+    let name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().to_string();
 
-            //let the user repeat if the input was wrong
-            while delete_option != "y" && delete_option != "n" {
-            println!("-----------------------------------------------");
-            println!("Invalid option, please try again");
-            println!("-----------------------------------------------");
-            println!("Do you want to delete the files after archiving? (y/n)");
-            delete_option.clear();
-            std::io::stdin().read_line(&mut delete_option).unwrap();
-            delete_option = delete_option.trim().to_string();
-            }
-  
-          if delete_option == "y" {
-              println!("\x1b[31m Warning - This action is irreversible!\x1b[37m");
-              println!("-----------------------------------------------");
-              println!("Are you sure you want to delete the files? (y/n)");
-              let mut delete_confirmation = String::new();
-              std::io::stdin().read_line(&mut delete_confirmation).unwrap();
-              delete_confirmation = delete_confirmation.trim().to_string();
+    println!("-----------------------------------------------");
+    for (archive, reasons) in &plan.keep {
+        let reasons: Vec<String> = reasons.iter().map(ToString::to_string).collect();
+        println!("Keep: {} ({})", name(&archive.path), reasons.join(", "));
+    }
+    for archive in &plan.remove {
+        println!("\x1b[33mRemove: {}\x1b[37m", name(&archive.path));
+    }
+    for path in &plan.foreign {
+        println!("Not made by the archivist, left alone: {}", name(path));
+    }
+    println!("-----------------------------------------------");
+    println!("Archives kept [{}], to remove [{}]", plan.keep.len(), plan.remove.len());
+    println!("-----------------------------------------------");
+}
 
-            //let the user repeat if the input was wrong
-             while delete_option != "y" && delete_option != "n" {
-            println!("-----------------------------------------------");
-            println!("Invalid option, please try again");
-            println!("-----------------------------------------------");
-            println!("Do you want to delete the files after archiving? (y/n)");
-            delete_option.clear();
-            std::io::stdin().read_line(&mut delete_option).unwrap();
-            delete_option = delete_option.trim().to_string();
+/// Removes the archives of a prune plan. Returns whether all were removed.
+fn remove_archives(plan: &PrunePlan) -> bool {
+    let mut complete = true;
+    for (path, result) in plan.execute() {
+        match result {
+            Ok(()) => println!("Removed: {}", path.display()),
+            Err(err) => {
+                complete = false;
+                eprintln!("\x1b[31mError removing archive {}\x1b[37m", err);
             }
-  
-              if delete_confirmation == "y" {
-                  for entry in fs::read_dir(&folder_location).unwrap() {
-                      let entry = entry.unwrap();
-                      let path = entry.path();
-                      if let Err(_err) = fs::remove_file(&path) {
-                          //eprintln!("Error deleting file {}: {}", path.display(), err);
-                      }
-                  }
-              }
-          }
-
+        }
     }
+    complete
+}
 
+/// Removes old archives by the retention rules of the arguments.
+fn prune(args: PruneArgs) {
+    let Some(retention) = args.retention.retention() else {
+        eprintln!("\x1b[31mNo retention rules given, use --keep-last, --keep-daily, --keep-weekly, --keep-monthly or --max-age\x1b[37m");
+        std::process::exit(1);
+    };
 
-    if option == "3" {
-        println!("-----------------------------------------------");
-        println!("Please enter the file endings separated by comma: ");
-        let mut file_endings_input = String::new();
-        std::io::stdin().read_line(&mut file_endings_input).unwrap();
-        file_endings_input = file_endings_input.trim().to_string();
-        let mut file_endings_input: Vec<String> = file_endings_input.split(',').map(|s| s.trim().to_string()).collect();
-        println!("File endings input: {:?}", file_endings_input);
-
-        // check if the file endings input are valid and if not let the user try again
-        let mut valid_input = true;
-        for file_ending in &file_endings_input {
-            if !file_endings.contains(file_ending) {
-                valid_input = false;
-                break;
-            }
+    let archive_folder = archive_folder(&args.folder);
+    let plan = match retention.plan(&archive_folder, Local::now()) {
+        Ok(plan) => plan,
+        Err(err) => {
+            eprintln!("\x1b[31mError reading the archives: {}\x1b[37m", err);
+            std::process::exit(1);
         }
+    };
+    print_prune_plan(&plan);
 
-        while !valid_input {
-            println!("-----------------------------------------------");
-            println!("Invalid file endings, please try again");
-            println!("-----------------------------------------------");
-            println!("Please enter the file endings separated by comma: ");
-            file_endings_input.clear();
-            let mut file_endings_input2: String = String::new();
-            std::io::stdin().read_line(&mut file_endings_input2).unwrap();
-            file_endings_input2 = file_endings_input2.trim().to_string();
-            file_endings_input = file_endings_input2.split(',').map(|s| s.trim().to_string()).collect();
-            println!("File endings input: {:?}", file_endings_input);
+    if args.dry_run {
+        println!("\x1b[33mDry run - no archives are removed\x1b[37m");
+        return;
+    }
+    if plan.remove.is_empty() {
+        return;
+    }
+    if !args.yes && !ask_yes_no(&format!("Remove {} archives permanently?", plan.remove.len())) {
+        return;
+    }
+    if !remove_archives(&plan) {
+        std::process::exit(1);
+    }
+}
 
-            valid_input = true;
-            for file_ending in &file_endings_input {
-                if !file_endings.contains(file_ending) {
-                    valid_input = false;
-                    break;
-                }
-            }
+/// Creates a new identity and writes it to the output file, or prints it.
+fn keygen(args: KeygenArgs) {
+    let identity = Identity::generate();
+    let contents = identity.to_file_contents();
+
+    let Some(output) = &args.output else {
+        print!("{}", contents.as_str());
+        return;
+    };
+    //never replace an existing identity, the archives encrypted to it
+    //couldn't be read anymore
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options.open(output).and_then(|mut file| file.write_all(contents.as_bytes()));
+    if let Err(err) = written {
+        eprintln!("\x1b[31mError writing {}: {}\x1b[37m", output.display(), err);
+        std::process::exit(1);
+    }
+    println!("-----------------------------------------------");
+    println!("Identity written to \x1b[96m{}\x1b[37m", output.display());
+    println!("Public key: {}", identity.to_public());
+    println!("-----------------------------------------------");
+}
+
+/// Prints the logo and a short description of the tool.
+fn print_banner() {
+    //give the console print a color blue
+    println!("\x1b[34m");
+    //
+    println!(r" _____ _               _             _     _       _     _     _ ");
+    println!(r"|_   _| |__   ___     / \   _ __ ___| |__ (_)_   _(_)___| |_  | |");
+    println!(r"  | | | '_ \ / _ \   / _ \ | '__/ __| '_ \| \ \ / / / __| __| | |");
+    println!(r"  | | | | | |  __/  / ___ \| | | (__| | | | |\ V /| \__ \ |_  |_|");
+    println!(r"  |_| |_| |_|\___| /_/   \_\_|  \___|_| |_|_| \_/ |_|___/\__| (_)");
+
+    //give the console print a color gray
+    println!("\x1b[90m");
+
+    println!("\nBuild: {}", the_archivist::VERSION);
+    //println!("\n2024\n");
+    println!("This tool will archive files in a folder and optionally delete them after archiving");
+    println!("Folders can be archived entirely, by file type or by identifier string\n");
+    println!("For more information visit: https://github.com/tkuerschner/the_archivist");
+
+    //give the console print a color white
+    println!("\x1b[37m");
+}
+
+fn run(settings: Settings) {
+
+    #[cfg(windows)]
+    colored::control::set_virtual_terminal(true).unwrap();
+
+    if settings.interactive {
+        print_banner();
+    }
+
+    let mut folder_location = match &settings.folder {
+        Some(folder) => folder.clone(),
+        None => {
+            print!("\nPlease enter the folder location: ");
+            io::stdout().flush().unwrap();
+            read_input()
+        }
+    };
+
+    let recursive = match settings.recursive {
+        Some(recursive) => recursive,
+        None => ask_yes_no("Do you want to include subfolders?"),
+    };
+
+    let scan = loop {
+        let mut scanner = Scanner::new(&folder_location).recursive(recursive).gitignore(settings.gitignore)
+            .binaries(settings.binaries.clone());
+        if let Some(destination) = &settings.destination {
+            scanner = scanner.archive_folder(destination);
+        }
+        if let Some(age) = settings.age {
+            scanner = scanner.age(age);
         }
-  
-
-        // create a zip file for each file types from file_endings_input separated by comma, naming convention is filetype_archive_year_month_day_hour_minute.zip
-        for file_ending in &file_endings_input {
-            let now = Local::now();
-            let zip_name = format!(
-                "{}_archive_{}_{}_{}_{}_{}.zip",
-                file_ending,
-                now.year(),
-                now.month(),
-                now.day(),
-                now.hour(),
-                now.minute()
-            );
-            let zip_path = archive_folder.join(&zip_name);
-            let zip_file = match fs::File::create(&zip_path) {
-                Ok(file) => file,
-                Err(err) => {
-                    eprintln!("Error creating zip file {}: {}", zip_path.display(), err);
+        if let Some(size) = settings.min_size {
+            scanner = scanner.min_size(size);
+        }
+        if let Some(size) = settings.max_size {
+            scanner = scanner.max_size(size);
+        }
+        match scanner.scan() {
+            Ok(scan) => break scan,
+            Err(err) => {
+                eprintln!("Error reading directory: {}", err);
+                if !settings.interactive {
                     std::process::exit(1);
                 }
-            };
-            let mut zip = ZipWriter::new(zip_file);
-            let options = FileOptions::default().compression_method(CompressionMethod::Stored);
-
-            for entry in fs::read_dir(&folder_location).unwrap() {
-                let entry = entry.unwrap();
-                let path = entry.path();
-                if let Some(extension) = path.extension() {
-                    if extension.to_str().unwrap() == file_ending {
-                        let file_name = path.file_name().unwrap().to_str().unwrap();
-                        if let Err(err) = zip.start_file(file_name, options) {
-                            eprintln!("Error starting file in zip {}: {}", file_name, err);
-                            continue;
-                        }
-                        let mut file = match fs::File::open(&path) {
-                            Ok(file) => file,
-                            Err(err) => {
-                                eprintln!("Error opening file {}: {}", path.display(), err);
-                                continue;
-                            }
-                        };
-                        if let Err(err) = io::copy(&mut file, &mut zip) {
-                            eprintln!("Error copying file {} to zip: {}", path.display(), err);
-                        }
-                    }
-                }
+                print!("Please re-enter the folder location: ");
+                io::stdout().flush().unwrap();
+                folder_location = read_input();
             }
+        }
+    };
 
-            if let Err(err) = zip.finish() {
-                eprintln!("Error finishing zip file {}: {}", zip_path.display(), err);
-            }
-            println!("-----------------------------------------------");
-            println!("Archive created: \x1b[96m{}\x1b[37m", zip_path.display());
-            println!("-----------------------------------------------");
+    println!("-----------------------------------------------");
+    println!("Number of files in the folder [{}]", scan.file_count);
+    println!("-----------------------------------------------\n");
+
+    let binaries = scan.binaries();
+    if !binaries.is_empty() {
+        println!("Binary files detected, excluding them from archiving [{}]", binaries.len());
+        for (name, class) in &binaries {
+            println!("\x1b[33m    {} ({})\x1b[37m", name, class);
         }
+        println!("-----------------------------------------------\n");
+    }
 
-        //ask the user if they want to delete the files after archiving buu only for the selected file types and add an additional warning and confirmation
-        println!("Do you want to delete the files after archiving? (y/n)");
-        let mut delete_option = String::new();
-        std::io::stdin().read_line(&mut delete_option).unwrap();
-        delete_option = delete_option.trim().to_string();
+    if !scan.own_archive.is_empty() {
+        println!("Binary files archived separately [{}]", scan.own_archive.len());
+        for (class, file) in &scan.own_archive {
+            println!("\x1b[96m    {} ({})\x1b[37m", file.name, class);
+        }
+        println!("-----------------------------------------------\n");
+    }
 
-          //let the user repeat if the input was wrong
-          while delete_option != "y" && delete_option != "n" {
-            println!("-----------------------------------------------");
-            println!("Invalid option, please try again");
-            println!("-----------------------------------------------");
-            println!("Do you want to delete the files after archiving? (y/n)");
-            delete_option.clear();
-            std::io::stdin().read_line(&mut delete_option).unwrap();
-            delete_option = delete_option.trim().to_string();
+    let filtered = scan.filtered();
+    if !filtered.is_empty() {
+        println!("Files skipped because of their age or size [{}]", filtered.len());
+        for (name, reason) in &filtered {
+            println!("\x1b[33m    {} ({})\x1b[37m", name, reason);
         }
+        println!("-----------------------------------------------\n");
+    }
 
-        if delete_option == "y" {
-            println!("\x1b[31m Warning - This action is irreversible!\x1b[37m");
-            println!("-----------------------------------------------");
-            println!("Are you sure you want to delete the files? (y/n)");
-            let mut delete_confirmation = String::new();
-            std::io::stdin().read_line(&mut delete_confirmation).unwrap();
-            delete_confirmation = delete_confirmation.trim().to_string();
+    let unreadable = scan.unreadable();
+    if !unreadable.is_empty() {
+        println!("\x1b[31mFiles that could not be read, skipping them [{}]\x1b[37m", unreadable.len());
+        for name in &unreadable {
+            println!("\x1b[31m    {}\x1b[37m", name);
+        }
+        println!("-----------------------------------------------\n");
+    }
 
-              //let the user repeat if the input was wrong
-            while delete_option != "y" && delete_option != "n" {
-            println!("-----------------------------------------------");
-            println!("Invalid option, please try again");
-            println!("-----------------------------------------------");
-            println!("Do you want to delete the files after archiving? (y/n)");
-            delete_option.clear();
-            std::io::stdin().read_line(&mut delete_option).unwrap();
-            delete_option = delete_option.trim().to_string();
-            }
+    println!("Filetypes detected:\x1b[96m {:?}", scan.file_endings);
+    println!("\x1b[37m");
+    println!("-----------------------------------------------\n");
+
+    //display the amount of files for each file type
+    for file_ending in &scan.file_endings {
+        let file_count = scan.files_with_extension(file_ending).count();
+        let total_size: u64 = scan.files_with_extension(file_ending).map(|file| file.size).sum();
+        println!(
+            "Number of\x1b[96m [*.{}]\x1b[37m files in the folder [{}], total size [{}]\n",
+            file_ending,
+            file_count,
+            format_size(total_size)
+        );
+    }
+
+    let mode = match settings.mode {
+        Some(mode) => mode,
+        None => ask_mode(),
+    };
+
+    let mut plan = match mode {
+        Mode::All => ArchivePlan::all(&scan),
+        Mode::PerType => ArchivePlan::per_type(&scan),
+        Mode::ByExtension => {
+            let mut file_endings_input = match &settings.extensions {
+                Some(extensions) => extensions.clone(),
+                None => ask_file_endings(),
+            };
+            println!("File endings input: {:?}", file_endings_input);
 
-            if delete_confirmation == "y" {
-                for entry in fs::read_dir(&folder_location).unwrap() {
-                    let entry = entry.unwrap();
-                    let path = entry.path();
-                    if let Some(extension) = path.extension() {
-                        if file_endings_input.contains(&extension.to_str().unwrap().to_string()) {
-                            if let Err(err) = fs::remove_file(&path) {
-                                eprintln!("Error deleting file {}: {}", path.display(), err);
-                            }
+            // check if the file endings input are valid and if not let the user try again
+            loop {
+                match ArchivePlan::by_extension(&scan, &file_endings_input) {
+                    Ok(plan) => break plan,
+                    Err(Error::UnknownExtension(_)) => {
+                        let unknown: Vec<String> = file_endings_input
+                            .iter()
+                            .filter(|file_ending| !scan.file_endings.contains(file_ending))
+                            .map(|file_ending| format!("*.{}", file_ending))
+                            .collect();
+                        if !settings.interactive {
+                            eprintln!("\x1b[31mError: no files in the folder end in {}\x1b[37m", unknown.join(", "));
+                            std::process::exit(1);
                         }
+                        println!("-----------------------------------------------");
+                        println!("No files end in {}, please try again", unknown.join(", "));
+                        println!("-----------------------------------------------");
+                        file_endings_input = ask_file_endings();
+                        println!("File endings input: {:?}", file_endings_input);
+                    }
+                    Err(err) => {
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
                     }
                 }
             }
         }
+        Mode::ByName => {
+            let matcher = match &settings.name {
+                Some(matcher) => matcher.clone(),
+                None => {
+                    println!("-----------------------------------------------");
+                    println!("Please enter the string: ");
+                    NameMatcher::contains(&read_input())
+                }
+            };
+            println!("String input: {:?}", matcher.to_string());
+            //the archived files are the only ones ever removed, so removing
+            //uses this matcher as well
+            ArchivePlan::by_matcher(&scan, &matcher)
+        }
+    };
+
+    if let Some(destination) = &settings.destination {
+        plan = plan.destination(destination);
+    }
+    if let Some(template) = &settings.name_template {
+        plan = plan.name_template(template);
+    }
+    //an existing archive with the same name is never overwritten
+    plan = plan.unique_names(settings.format);
+
+    if settings.dry_run {
+        print_plan(&scan, &plan, &settings);
+        if let Some(retention) = settings.retention {
+            println!("Archives the prune after the run would keep and remove:");
+            match retention.plan(&plan.archive_folder, Local::now()) {
+                Ok(prune_plan) => print_prune_plan(&prune_plan),
+                Err(err) => eprintln!("\x1b[31mError reading the archives: {}\x1b[37m", err),
+            }
+        }
+        return;
     }
 
-    if option == "4" {
-        println!("-----------------------------------------------");
-        println!("Please enter the string: ");
-        let mut string_input = String::new();
-        std::io::stdin().read_line(&mut string_input).unwrap();
-        string_input = string_input.trim().to_string();
-        println!("String input: {:?}", string_input);
-    
-        // create a zip file for each file types from file_endings_input separated by comma, naming convention is filetype_archive_year_month_day_hour_minute.zip
-        let now = Local::now();
-        let zip_name = format!(
-            "{}_archive_{}_{}_{}_{}_{}.zip",
-            string_input,
-            now.year(),
-            now.month(),
-            now.day(),
-            now.hour(),
-            now.minute()
-        );
-        let zip_path = archive_folder.join(&zip_name);
-        let zip_file = match fs::File::create(&zip_path) {
-            Ok(file) => file,
+    let password = if settings.encrypt {
+        if settings.format.is_tar() {
+            eprintln!("\x1b[31mError: {}\x1b[37m", Error::EncryptionUnsupported(settings.format));
+            std::process::exit(1);
+        }
+        match secret::password(settings.password_fd, true) {
+            Ok(password) => Some(password),
             Err(err) => {
-                eprintln!("Error creating zip file {}: {}", zip_path.display(), err);
+                eprintln!("\x1b[31mError: {}\x1b[37m", err);
                 std::process::exit(1);
             }
-        };
-        let mut zip = ZipWriter::new(zip_file);
-        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
-    
-        for entry in fs::read_dir(&folder_location).unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if let Some(file_name) = path.file_name() {
-                if file_name.to_str().unwrap().contains(&string_input) {
-                    if let Err(err) = zip.start_file(file_name.to_str().unwrap(), options) {
-                        eprintln!("Error starting file in zip {}: {}", file_name.to_str().unwrap(), err);
-                        continue;
-                    }
-                    let mut file = match fs::File::open(&path) {
-                        Ok(file) => file,
-                        Err(err) => {
-                            eprintln!("Error opening file {}: {}", path.display(), err);
-                            continue;
-                        }
-                    };
-                    if let Err(err) = io::copy(&mut file, &mut zip) {
-                        eprintln!("Error copying file {} to zip: {}", path.display(), err);
-                    }
-                }
-            }
         }
-        if let Err(err) = zip.finish() {
-            eprintln!("Error finishing zip file {}: {}", zip_path.display(), err);
+    } else {
+        None
+    };
+
+    let archiver = Archiver::new()
+        .compression(settings.compression)
+        .policy(settings.policy.clone())
+        .format(settings.format)
+        .volume_size(settings.volume_size)
+        .password(password)
+        .recipients(settings.recipients.clone())
+        .jobs(settings.jobs.unwrap_or(0))
+        .catalog(true);
+
+    //archives written at the same time are announced as they are finished,
+    //the summary below follows the order of the plan
+    println!("-----------------------------------------------");
+    let mut finished = 0;
+    let progress = |archive: &Result<ArchiveResult, Error>| {
+        finished += 1;
+        match archive {
+            Ok(archive) => println!(
+                "Archive written [{}/{}]: \x1b[96m{}\x1b[37m",
+                finished,
+                plan.archives.len(),
+                archive.path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            Err(err) => eprintln!("\x1b[31mArchive failed [{}/{}]: {}\x1b[37m", finished, plan.archives.len(), err),
         }
-        println!("-----------------------------------------------");
-        println!("Archive created: \x1b[96m{}\x1b[37m", zip_path.display());
-        println!("-----------------------------------------------");
-
-        //ask the user if they want to delete the files after archiving buu only for the selected file types and add an additional warning and confirmation
-        println!("Do you want to delete the files after archiving? (y/n)");
-        let mut delete_option = String::new();
-        std::io::stdin().read_line(&mut delete_option).unwrap();
-        delete_option = delete_option.trim().to_string();
-
-          //let the user repeat if the input was wrong
-          while delete_option != "y" && delete_option != "n" {
-            println!("-----------------------------------------------");
-            println!("Invalid option, please try again");
-            println!("-----------------------------------------------");
-            println!("Do you want to delete the files after archiving? (y/n)");
-            delete_option.clear();
-            std::io::stdin().read_line(&mut delete_option).unwrap();
-            delete_option = delete_option.trim().to_string();
+    };
+    let mut report = match archiver.execute_with_progress(&plan, progress) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Error creating archive: {}", err);
+            std::process::exit(1);
         }
-
-        if delete_option == "y" {
-            println!("\x1b[31m Warning - This action is irreversible!\x1b[37m");
-            println!("-----------------------------------------------");
-            println!("Are you sure you want to delete the files? (y/n)");
-            let mut delete_confirmation = String::new();
-            std::io::stdin().read_line(&mut delete_confirmation).unwrap();
-            delete_confirmation = delete_confirmation.trim().to_string();
-
-              //let the user repeat if the input was wrong
-            while delete_option != "y" && delete_option != "n" {
-            println!("-----------------------------------------------");
-            println!("Invalid option, please try again");
-            println!("-----------------------------------------------");
-            println!("Do you want to delete the files after archiving? (y/n)");
-            delete_option.clear();
-            std::io::stdin().read_line(&mut delete_option).unwrap();
-            delete_option = delete_option.trim().to_string();
+    };
+
+    //the verified files are marked in the catalog, so a watch doesn't archive
+    //them again
+    let mut catalog = match Catalog::open(&plan.archive_folder) {
+        Ok(catalog) => Some(catalog),
+        Err(err) => {
+            eprintln!("\x1b[31mError opening the catalog: {}\x1b[37m", err);
+            None
+        }
+    };
+    for archive in &mut report.archives {
+        //the files of a failed archive are kept, the other archives go on
+        let archive = match archive {
+            Ok(archive) => archive,
+            Err(err) => {
+                println!("-----------------------------------------------");
+                eprintln!("\x1b[31mError creating archive: {}\x1b[37m", err);
+                println!("-----------------------------------------------");
+                continue;
             }
-
-            if delete_confirmation == "y" {
-                for entry in fs::read_dir(&folder_location).unwrap() {
-                    let entry = entry.unwrap();
-                    let path = entry.path();
-                    if let Some(file_name) = path.file_name() {
-                        if file_name.to_str().unwrap().contains(&string_input) {
-                            if let Err(err) = fs::remove_file(&path) {
-                                eprintln!("Error deleting file {}: {}", path.display(), err);
-                            }
-                        }
-                    }
-                }
+        };
+        for (path, err) in &archive.errors {
+            eprintln!("Error archiving file {}: {}", path.display(), err);
+        }
+        println!("-----------------------------------------------");
+        println!("Archive created: \x1b[96m{}\x1b[37m", archive.path.display());
+        if archive.is_encrypted() {
+            println!("Encryption: AES-256");
+        }
+        if archive.has_recipients() {
+            println!("Encrypted to recipients: [{}]", archive.recipients.len());
+        }
+        println!(
+            "Compression: {}, {} -> {} ({:.1}%)",
+            archive.compression,
+            format_size(archive.original_size),
+            format_size(archive.archive_size),
+            archive.compression_ratio() * 100.0
+        );
+        if archive.stored_files > 0 {
+            println!("Files stored without compression: [{}]", archive.stored_files);
+        }
+        if !archive.volumes.is_empty() {
+            println!("Volumes: [{}]", archive.volumes.len());
+            for volume in &archive.volumes {
+                println!("    {}", volume.file_name().unwrap_or_default().to_string_lossy());
             }
         }
 
+        //check the archive before anything gets deleted
+        let file_count = archive.files.len();
+        let verification = archive.verify();
+        println!("Files verified: [{}/{}]", verification.verified.len(), file_count);
+        for (path, reason) in &verification.failed {
+            println!("\x1b[31mVerification failed for {}: {}\x1b[37m", path.display(), reason);
+        }
+        if let Some(Err(err)) = catalog.as_mut().map(|catalog| catalog.record_verification(archive)) {
+            eprintln!("\x1b[31mError updating the catalog: {}\x1b[37m", err);
+        }
+        println!("-----------------------------------------------");
+    }
 
-
-
+    match after_archive(&settings) {
+        AfterArchive::Keep => {}
+        AfterArchive::Trash => print_cleanup_summary(&cleanup::trash_archived(&report)),
+        AfterArchive::Delete => print_cleanup_summary(&cleanup::delete_archived(&report)),
     }
 
-    // if any ignore_files were found, display them
+    //the rules were given with the run, so pruning isn't confirmed again
+    if let Some(retention) = settings.retention {
+        match retention.plan(&plan.archive_folder, Local::now()) {
+            Ok(prune_plan) => {
+                print_prune_plan(&prune_plan);
+                remove_archives(&prune_plan);
+            }
+            Err(err) => eprintln!("\x1b[31mError pruning the archives: {}\x1b[37m", err),
+        }
+    }
 
-    if !ignore_files.is_empty() {
+    // if any files were ignored, display them
+    if !scan.ignored().is_empty() {
         println!("-----------------------------------------------");
-        println!("Files that were ignored: {:?}", ignore_files);
+        println!("Files that were ignored: {:?}", scan.ignored());
         println!("-----------------------------------------------");
     }
 
     //keep the program running until the user presses enter
-    println!("Press enter to exit");
-    let mut exit = String::new();
-    std::io::stdin().read_line(&mut exit).unwrap();
-    
-
-    println!("Exiting...");
+    if settings.interactive {
+        println!("Press enter to exit");
+        read_input();
 
+        println!("Exiting...");
+    }
 
+    if report.failed().next().is_some() {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,332 @@
+//! The manifest written into every archive, so an archive can be checked and
+//! restored without knowing where or how it was made.
+
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::error::{Error, Result};
+use crate::format::{self, Format};
+use crate::password::{self, Password};
+use crate::plan::{ArchivePlan, PlannedArchive, Selection};
+use crate::scanner::FileEntry;
+use crate::volume;
+
+/// Name of the manifest entry inside the archives.
+pub const MANIFEST_NAME: &str = ".archivist/manifest.json";
+
+/// Where the files of an archive came from and how they were selected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of the tool that wrote the archive.
+    pub version: String,
+    pub hostname: String,
+    /// When the archive was written (RFC 3339), the time it is named by.
+    /// Archives of one run share it, however they were scheduled.
+    pub created: String,
+    /// Absolute path of the scanned folder.
+    pub folder: PathBuf,
+    /// Selection mode: `all`, `per-type`, `by-extension` or `by-name`.
+    pub mode: String,
+    /// What the archive holds: `full`, a file ending, an identifier or a
+    /// binary class. Missing in archives of older versions.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// File ending of the archive in the per-type and by-extension modes.
+    pub extension: Option<String>,
+    /// Patterns the file names match in by-name mode.
+    pub identifier: Option<String>,
+    /// File names that were never archived.
+    pub ignore: Vec<String>,
+    pub files: Vec<ManifestEntry>,
+}
+
+/// A single archived file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Name of the entry in the archive.
+    pub name: String,
+    /// Absolute path the file was archived from.
+    pub path: PathBuf,
+    pub size: u64,
+    /// Modification time (RFC 3339).
+    pub modified: Option<String>,
+    /// Unix permission bits.
+    pub permissions: Option<u32>,
+    /// SHA-256 of the contents, hex encoded.
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// An empty manifest for one archive of `plan`.
+    pub(crate) fn new(plan: &ArchivePlan, archive: &PlannedArchive) -> Self {
+        let folder = plan.folder.canonicalize().unwrap_or_else(|_| plan.folder.clone());
+        let (extension, identifier) = match &plan.selection {
+            Selection::All => (None, None),
+            Selection::PerType | Selection::ByExtension(_) => (Some(archive.label.clone()), None),
+            Selection::ByName(matcher) => (None, Some(matcher.to_string())),
+        };
+
+        Manifest {
+            version: crate::VERSION.to_string(),
+            hostname: hostname::get()
+                .map(|hostname| hostname.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            created: archive.created.to_rfc3339(),
+            folder,
+            mode: plan.selection.to_string(),
+            label: Some(archive.label.clone()),
+            extension,
+            identifier,
+            ignore: plan.ignore_files.clone(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Adds an archived file with the SHA-256 of its contents.
+    pub(crate) fn add(&mut self, entry: &FileEntry, metadata: Option<&fs::Metadata>, sha256: String) {
+        let modified = metadata
+            .and_then(|metadata| metadata.modified().ok())
+            .map(|modified| DateTime::<Local>::from(modified).to_rfc3339());
+
+        #[cfg(unix)]
+        let permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            metadata.map(|metadata| metadata.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let permissions = None;
+
+        self.files.push(ManifestEntry {
+            name: entry.name.clone(),
+            path: self.folder.join(&entry.name),
+            size: entry.size,
+            modified,
+            permissions,
+            sha256,
+        });
+    }
+
+    pub(crate) fn to_json(&self) -> Vec<u8> {
+        // the manifest only holds strings and numbers, so this can't fail
+        serde_json::to_vec_pretty(self).expect("manifest can be serialized")
+    }
+
+    /// The label of the archive, worked out from the selection for archives
+    /// of older versions.
+    pub fn label(&self) -> String {
+        self.label
+            .clone()
+            .or_else(|| self.extension.clone())
+            .or_else(|| self.identifier.clone())
+            .unwrap_or_else(|| "full".to_string())
+    }
+
+    /// The entry for `name`, if the file is listed.
+    pub fn file(&self, name: &str) -> Option<&ManifestEntry> {
+        self.files.iter().find(|file| file.name == name)
+    }
+}
+
+/// Reads the manifest of an archive. Returns `None` for archives without
+/// one, e.g. archives written by older versions.
+///
+/// Fails with [`Error::PasswordRequired`] for encrypted archives, see
+/// [`read_manifest_decrypt`], and with [`Error::IdentityRequired`] for
+/// archives encrypted to public keys.
+pub fn read_manifest(archive: &Path) -> Result<Option<Manifest>> {
+    read(archive, None)
+}
+
+/// Reads the manifest of an encrypted archive, like [`read_manifest`].
+pub fn read_manifest_decrypt(archive: &Path, password: &Password) -> Result<Option<Manifest>> {
+    read(archive, Some(password))
+}
+
+fn read(archive: &Path, password: Option<&Password>) -> Result<Option<Manifest>> {
+    let format = Format::from_path(archive).ok_or_else(|| Error::UnknownFormat(archive.to_path_buf()))?;
+    if format::is_encrypted(archive) {
+        return Err(Error::IdentityRequired(archive.to_path_buf()));
+    }
+    let file = volume::open(archive).map_err(|err| Error::io(archive, err))?;
+    read_from(archive, format, file, password)
+}
+
+/// Reads the manifest from `file`, the opened (and decrypted) `archive`.
+pub(crate) fn read_from(archive: &Path, format: Format, file: impl Read + Seek, password: Option<&Password>) -> Result<Option<Manifest>> {
+    let mut contents = Vec::new();
+    if format.is_tar() {
+        let reader = format::tar_reader(format, file).map_err(|err| Error::io(archive, err))?;
+        let mut tar = tar::Archive::new(reader);
+        let mut found = false;
+        for entry in tar.entries().map_err(|err| Error::io(archive, err))? {
+            let mut entry = entry.map_err(|err| Error::io(archive, err))?;
+            if entry.path_bytes().as_ref() == MANIFEST_NAME.as_bytes() {
+                entry.read_to_end(&mut contents).map_err(|err| Error::io(archive, err))?;
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            return Ok(None);
+        }
+    } else {
+        let mut zip = ZipArchive::new(file).map_err(|err| Error::zip(archive, err))?;
+        let entry = match password {
+            Some(password) => zip.by_name_decrypt(MANIFEST_NAME, password.as_bytes()),
+            None => zip.by_name(MANIFEST_NAME),
+        };
+        match entry {
+            Ok(mut entry) => entry.read_to_end(&mut contents).map_err(|err| Error::io(archive, err))?,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(password::decrypt_error(archive, err)),
+        };
+    }
+
+    parse(&contents).map(Some).map_err(|err| Error::io(archive, err))
+}
+
+pub(crate) fn parse(contents: &[u8]) -> io::Result<Manifest> {
+    serde_json::from_slice(contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("invalid manifest: {}", err)))
+}
+
+/// Passes reads through and computes the SHA-256 of everything read.
+pub(crate) struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        HashingReader {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// The hex encoded SHA-256 of everything read so far.
+    pub(crate) fn sha256(self) -> String {
+        hex::encode(self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::write::GzEncoder;
+    use zip::write::SimpleFileOptions;
+    use zip::{AesMode, ZipWriter};
+
+    use super::*;
+
+    fn manifest() -> Manifest {
+        Manifest {
+            version: crate::VERSION.to_string(),
+            hostname: "host".to_string(),
+            created: "2024-03-01T14:05:00+01:00".to_string(),
+            folder: PathBuf::from("/data/exports"),
+            mode: "per-type".to_string(),
+            label: Some("csv".to_string()),
+            extension: Some("csv".to_string()),
+            identifier: None,
+            ignore: vec!["Thumbs.db".to_string()],
+            files: vec![ManifestEntry {
+                name: "sales/march.csv".to_string(),
+                path: PathBuf::from("/data/exports/sales/march.csv"),
+                size: 5,
+                modified: Some("2024-02-29T10:00:00+01:00".to_string()),
+                permissions: Some(0o644),
+                sha256: hex::encode(Sha256::digest(b"a,b,c")),
+            }],
+        }
+    }
+
+    fn zip(password: Option<&str>) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        zip.start_file("sales/march.csv", options).unwrap();
+        zip.write_all(b"a,b,c").unwrap();
+        let options = match password {
+            Some(password) => options.with_aes_encryption(AesMode::Aes256, password),
+            None => options,
+        };
+        zip.start_file(MANIFEST_NAME, options).unwrap();
+        zip.write_all(&manifest().to_json()).unwrap();
+        zip.finish().unwrap()
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let manifest = manifest();
+        assert_eq!(parse(&manifest.to_json()).unwrap(), manifest);
+        assert!(parse(b"{}").is_err());
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let archive = Path::new("csv.zip");
+        assert_eq!(read_from(archive, Format::Zip, zip(None), None).unwrap(), Some(manifest()));
+
+        let password = Password::new("secret");
+        let read = read_from(archive, Format::Zip, zip(Some("secret")), Some(&password)).unwrap();
+        assert_eq!(read, Some(manifest()));
+        let read = read_from(archive, Format::Zip, zip(Some("secret")), Some(&Password::new("wrong")));
+        assert!(matches!(read, Err(Error::WrongPassword(_))));
+    }
+
+    #[test]
+    fn tar_round_trip() {
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        for (name, contents) in [("sales/march.csv", b"a,b,c".to_vec()), (MANIFEST_NAME, manifest().to_json())] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, name, contents.as_slice()).unwrap();
+        }
+        let file = Cursor::new(tar.into_inner().unwrap().finish().unwrap());
+        assert_eq!(read_from(Path::new("csv.tar.gz"), Format::TarGz, file, None).unwrap(), Some(manifest()));
+    }
+
+    #[test]
+    fn without_manifest() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("a.txt", SimpleFileOptions::default()).unwrap();
+        let file = zip.finish().unwrap();
+        assert_eq!(read_from(Path::new("old.zip"), Format::Zip, file, None).unwrap(), None);
+    }
+
+    #[test]
+    fn labels() {
+        let mut manifest = manifest();
+        assert_eq!(manifest.label(), "csv");
+        manifest.label = None;
+        manifest.extension = None;
+        assert_eq!(manifest.label(), "full");
+        manifest.identifier = Some("report".to_string());
+        assert_eq!(manifest.label(), "report");
+        assert!(manifest.file("sales/march.csv").is_some());
+        assert!(manifest.file("march.csv").is_none());
+    }
+
+    #[test]
+    fn hashing_reader() {
+        let mut reader = HashingReader::new(&b"a,b,c"[..]);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.sha256(), hex::encode(Sha256::digest(b"a,b,c")));
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+
+use crate::error::{Error, Result};
+
+/// Matches file names against include and exclude patterns.
+///
+/// Patterns are written as `kind:text`, where kind is `glob`, `re` (a
+/// regular expression), `prefix` or `suffix`; patterns without a kind match
+/// names that contain the text, e.g. `report`, `glob:*_2024-0[1-3]*.csv`,
+/// `re:^inv\d+`, `prefix:draft_` or `suffix:_old.txt`.
+///
+/// A name matches if it matches any of the include patterns (or there are
+/// none) and none of the exclude patterns.
+#[derive(Debug, Clone)]
+pub struct NameMatcher {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    ignore_case: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    /// The pattern as written.
+    source: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Contains(String),
+    Prefix(String),
+    Suffix(String),
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl NameMatcher {
+    /// Compiles the patterns. Fails if a glob or regular expression isn't
+    /// valid.
+    pub fn new(include: &[String], exclude: &[String], ignore_case: bool) -> Result<Self> {
+        let compile = |patterns: &[String]| -> Result<Vec<Pattern>> {
+            patterns.iter().map(|pattern| Pattern::new(pattern, ignore_case)).collect()
+        };
+
+        Ok(NameMatcher {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            ignore_case,
+        })
+    }
+
+    /// Matches names containing `text`, case-sensitive.
+    pub fn contains(text: &str) -> Self {
+        NameMatcher {
+            include: vec![Pattern {
+                source: text.to_string(),
+                kind: Kind::Contains(text.to_string()),
+            }],
+            exclude: Vec::new(),
+            ignore_case: false,
+        }
+    }
+
+    pub fn is_match(&self, file_name: &str) -> bool {
+        let name = if self.ignore_case {
+            file_name.to_lowercase()
+        } else {
+            file_name.to_string()
+        };
+
+        let included = self.include.is_empty() || self.include.iter().any(|pattern| pattern.is_match(&name));
+        included && !self.exclude.iter().any(|pattern| pattern.is_match(&name))
+    }
+
+    /// A name for the archive that can be used in a file name: the text of
+    /// plain patterns, `matched` for anything else.
+    pub fn label(&self) -> String {
+        let texts: Vec<&str> = self
+            .include
+            .iter()
+            .map(|pattern| match &pattern.kind {
+                Kind::Contains(text) => Some(pattern.source.as_str()).filter(|_| !text.is_empty()),
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .unwrap_or_default();
+
+        let label = texts.join("_");
+        let safe = !label.is_empty()
+            && self.exclude.is_empty()
+            && label.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '));
+        if safe {
+            label
+        } else {
+            "matched".to_string()
+        }
+    }
+}
+
+impl Pattern {
+    fn new(source: &str, ignore_case: bool) -> Result<Self> {
+        let fold = |text: &str| if ignore_case { text.to_lowercase() } else { text.to_string() };
+
+        let kind = match source.split_once(':') {
+            Some(("glob", glob)) => Kind::Glob(
+                GlobBuilder::new(glob)
+                    .case_insensitive(ignore_case)
+                    .build()
+                    .map_err(|err| Error::InvalidPattern(format!("{}: {}", source, err)))?
+                    .compile_matcher(),
+            ),
+            Some(("re", regex)) => Kind::Regex(
+                RegexBuilder::new(regex)
+                    .case_insensitive(ignore_case)
+                    .build()
+                    .map_err(|err| Error::InvalidPattern(format!("{}: {}", source, err)))?,
+            ),
+            Some(("prefix", prefix)) => Kind::Prefix(fold(prefix)),
+            Some(("suffix", suffix)) => Kind::Suffix(fold(suffix)),
+            _ => Kind::Contains(fold(source)),
+        };
+
+        Ok(Pattern {
+            source: source.to_string(),
+            kind,
+        })
+    }
+
+    /// `name` is already lowercase when matching case-insensitive.
+    fn is_match(&self, name: &str) -> bool {
+        match &self.kind {
+            Kind::Contains(text) => name.contains(text.as_str()),
+            Kind::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            Kind::Suffix(suffix) => name.ends_with(suffix.as_str()),
+            Kind::Glob(glob) => glob.is_match(name),
+            Kind::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+impl fmt::Display for NameMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let include: Vec<&str> = self.include.iter().map(|pattern| pattern.source.as_str()).collect();
+        write!(f, "{}", include.join(", "))?;
+        if !self.exclude.is_empty() {
+            let exclude: Vec<&str> = self.exclude.iter().map(|pattern| pattern.source.as_str()).collect();
+            write!(f, " excluding {}", exclude.join(", "))?;
+        }
+        if self.ignore_case {
+            write!(f, " (ignoring case)")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(include: &[&str], exclude: &[&str], ignore_case: bool) -> NameMatcher {
+        let strings = |patterns: &[&str]| patterns.iter().map(|pattern| pattern.to_string()).collect::<Vec<_>>();
+        NameMatcher::new(&strings(include), &strings(exclude), ignore_case).unwrap()
+    }
+
+    #[test]
+    fn kinds() {
+        assert!(matcher(&["report"], &[], false).is_match("q1_report.pdf"));
+        assert!(!matcher(&["report"], &[], false).is_match("Report.pdf"));
+        assert!(matcher(&["prefix:draft_"], &[], false).is_match("draft_1.txt"));
+        assert!(!matcher(&["prefix:draft_"], &[], false).is_match("old_draft_1.txt"));
+        assert!(matcher(&["suffix:_old.txt"], &[], false).is_match("notes_old.txt"));
+        assert!(!matcher(&["suffix:_old.txt"], &[], false).is_match("notes_old.txt.bak"));
+        assert!(matcher(&["glob:*_2024-0[1-3]*.csv"], &[], false).is_match("sales_2024-02-10.csv"));
+        assert!(!matcher(&["glob:*_2024-0[1-3]*.csv"], &[], false).is_match("sales_2024-04-10.csv"));
+        assert!(matcher(&[r"re:^inv\d+"], &[], false).is_match("inv0042.pdf"));
+        assert!(!matcher(&[r"re:^inv\d+"], &[], false).is_match("my_inv0042.pdf"));
+        //unknown kinds are plain text
+        assert!(matcher(&["note:1"], &[], false).is_match("note:1.txt"));
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let everything = matcher(&[], &[], false);
+        assert!(everything.is_match("anything"));
+
+        let matcher = matcher(&["report", "suffix:.csv"], &["glob:*.tmp.*", "draft"], false);
+        assert!(matcher.is_match("report.pdf"));
+        assert!(matcher.is_match("sales.csv"));
+        assert!(!matcher.is_match("notes.txt"));
+        assert!(!matcher.is_match("report.tmp.pdf"));
+        assert!(!matcher.is_match("draft_sales.csv"));
+    }
+
+    #[test]
+    fn ignore_case() {
+        for pattern in ["REPORT", "prefix:Rep", "suffix:.PDF", "glob:*.PDF", "re:^rep"] {
+            assert!(matcher(&[pattern], &[], true).is_match("Report.pdf"), "{}", pattern);
+        }
+        assert!(!matcher(&["report"], &["PDF"], true).is_match("Report.pdf"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(NameMatcher::new(&["re:(".to_string()], &[], false).is_err());
+        assert!(NameMatcher::new(&[], &["glob:[a".to_string()], false).is_err());
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(NameMatcher::contains("report").label(), "report");
+        assert_eq!(matcher(&["report", "invoice"], &[], false).label(), "report_invoice");
+        assert_eq!(matcher(&["glob:*.csv"], &[], false).label(), "matched");
+        assert_eq!(matcher(&["report"], &["draft"], false).label(), "matched");
+        assert_eq!(matcher(&["a/b"], &[], false).label(), "matched");
+        assert_eq!(matcher(&[], &[], false).label(), "matched");
+    }
+
+    #[test]
+    fn display() {
+        let matcher = matcher(&["report", "glob:*.csv"], &["draft"], true);
+        assert_eq!(matcher.to_string(), "report, glob:*.csv excluding draft (ignoring case)");
+    }
+}
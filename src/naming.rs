@@ -0,0 +1,162 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, Timelike};
+
+/// Placeholders a [`NameTemplate`] can use.
+const PLACEHOLDERS: &[&str] = &["label", "folder", "date", "time", "year", "month", "day", "hour", "minute"];
+
+/// Template for the file names of the archives, without the format's
+/// extension.
+///
+/// Placeholders are written in braces: `{label}` (what the archive holds,
+/// e.g. `full` or a file ending), `{folder}` (name of the archived folder),
+/// `{date}` (`2024-03-01`), `{time}` (`14-05`) and `{year}`, `{month}`,
+/// `{day}`, `{hour}` and `{minute}`. Every template has to contain
+/// `{label}`, so the archives of a run get different names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    template: String,
+}
+
+impl Default for NameTemplate {
+    /// `{label}_archive_{year}_{month}_{day}_{hour}_{minute}`, the names the
+    /// archivist has always used.
+    fn default() -> Self {
+        NameTemplate {
+            template: "{label}_archive_{year}_{month}_{day}_{hour}_{minute}".to_string(),
+        }
+    }
+}
+
+impl NameTemplate {
+    /// The name of an archive holding `label`, made from `folder` at `now`.
+    pub fn render(&self, label: &str, folder: &str, now: &DateTime<Local>) -> String {
+        let mut name = String::new();
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            name.push_str(&rest[..start]);
+            // the placeholders were checked when parsing
+            let end = rest[start..].find('}').map_or(rest.len(), |end| start + end);
+            let value = match &rest[start + 1..end] {
+                "label" => label.to_string(),
+                "folder" => folder.to_string(),
+                "date" => now.format("%Y-%m-%d").to_string(),
+                "time" => now.format("%H-%M").to_string(),
+                "year" => now.year().to_string(),
+                "month" => now.month().to_string(),
+                "day" => now.day().to_string(),
+                "hour" => now.hour().to_string(),
+                "minute" => now.minute().to_string(),
+                _ => String::new(),
+            };
+            name.push_str(&value);
+            rest = rest.get(end + 1..).unwrap_or_default();
+        }
+        name.push_str(rest);
+        name
+    }
+}
+
+/// Splits a name made with the default template, like
+/// `csv_archive_2024_3_1_14_5` (optionally followed by `_1`, `_2`, ... for
+/// archives made in the same minute), into its label and time.
+pub fn parse_default_name(name: &str) -> Option<(String, NaiveDateTime)> {
+    let (label, time) = name.rsplit_once("_archive_")?;
+    let parts: Vec<u32> = time.split('_').map(str::parse).collect::<Result<_, _>>().ok()?;
+    if label.is_empty() || !(5..=6).contains(&parts.len()) {
+        return None;
+    }
+    let date = NaiveDate::from_ymd_opt(parts[0] as i32, parts[1], parts[2])?;
+    Some((label.to_string(), date.and_hms_opt(parts[3], parts[4], 0)?))
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if input.contains(['/', '\\']) {
+            return Err(format!("invalid name template '{}' (must not contain a path separator)", input));
+        }
+
+        let mut has_label = false;
+        let mut rest = input;
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                return Err(format!("invalid name template '{}' (unmatched '}}')", input));
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("invalid name template '{}' (unmatched '{{')", input))?;
+            let placeholder = &rest[start + 1..end];
+            if !PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!(
+                    "unknown placeholder '{{{}}}' (expected one of {})",
+                    placeholder,
+                    PLACEHOLDERS.iter().map(|known| format!("{{{}}}", known)).collect::<Vec<_>>().join(", ")
+                ));
+            }
+            has_label |= placeholder == "label";
+            rest = &rest[end + 1..];
+        }
+
+        if !has_label {
+            return Err(format!("invalid name template '{}' (must contain {{label}})", input));
+        }
+        Ok(NameTemplate {
+            template: input.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn time() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 1, 14, 5, 0).unwrap()
+    }
+
+    #[test]
+    fn default_names() {
+        let name = NameTemplate::default().render("csv", "exports", &time());
+        assert_eq!(name, "csv_archive_2024_3_1_14_5");
+        let expected = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(14, 5, 0).unwrap();
+        assert_eq!(parse_default_name(&name), Some(("csv".to_string(), expected)));
+    }
+
+    #[test]
+    fn render() {
+        let template: NameTemplate = "{folder}-{label}-{date}_{time}".parse().unwrap();
+        assert_eq!(template.render("full", "exports", &time()), "exports-full-2024-03-01_14-05");
+        let template: NameTemplate = "{label}".parse().unwrap();
+        assert_eq!(template.render("pdf", "exports", &time()), "pdf");
+        assert_eq!(template.to_string(), "{label}");
+    }
+
+    #[test]
+    fn invalid_templates() {
+        for input in ["", "{folder}", "{label", "label}", "{label}{", "{label}_{week}", "{label}/{date}", "a\\{label}"] {
+            assert!(input.parse::<NameTemplate>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_default_names() {
+        let expected = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap().and_hms_opt(23, 59, 0).unwrap();
+        assert_eq!(parse_default_name("my_archive_2024_12_31_23_59_2"), Some(("my".to_string(), expected)));
+        assert_eq!(parse_default_name("full_archive_2024_12_31_23_59").map(|(label, _)| label), Some("full".to_string()));
+        for name in ["_archive_2024_3_1_14_5", "csv_archive_2024_3_1", "csv_archive_2024_13_1_14_5", "csv_2024_3_1_14_5"] {
+            assert_eq!(parse_default_name(name), None, "{}", name);
+        }
+    }
+}
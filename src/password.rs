@@ -0,0 +1,58 @@
+use std::fmt;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use zeroize::Zeroizing;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::error::{Error, Result};
+
+/// Password of encrypted zip archives. It is wiped from memory when dropped
+/// and never shown in debug output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Password(Zeroizing<String>);
+
+impl Password {
+    pub fn new(password: impl Into<String>) -> Self {
+        Password(Zeroizing::new(password.into()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Password(..)")
+    }
+}
+
+/// Checks `password` against the first encrypted entry of `zip`, so a
+/// missing or wrong password fails the whole archive instead of every entry.
+pub(crate) fn check<R: Read + Seek>(zip: &mut ZipArchive<R>, archive: &Path, password: Option<&Password>) -> Result<()> {
+    let Some(index) = (0..zip.len()).find(|&index| zip.by_index_raw(index).is_ok_and(|entry| entry.encrypted())) else {
+        return Ok(());
+    };
+    let Some(password) = password else {
+        return Err(Error::PasswordRequired(archive.to_path_buf()));
+    };
+    match zip.by_index_decrypt(index, password.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(decrypt_error(archive, err)),
+    }
+}
+
+/// The error for a zip entry that couldn't be opened.
+pub(crate) fn decrypt_error(archive: &Path, err: ZipError) -> Error {
+    match err {
+        ZipError::InvalidPassword => Error::WrongPassword(archive.to_path_buf()),
+        ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) => Error::PasswordRequired(archive.to_path_buf()),
+        err => Error::zip(archive, err),
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+use crate::error::{Error, Result};
+use crate::binary::BinaryClass;
+use crate::format::{self, Format};
+use crate::matcher::NameMatcher;
+use crate::naming::NameTemplate;
+use crate::scanner::{FileEntry, ScanResult};
+use crate::volume;
+
+/// Which archives to create and which files go into each of them.
+///
+/// Plans are built from a [`ScanResult`] with one of the builders, which
+/// correspond to the options of the interactive menu.
+#[derive(Debug, Clone)]
+pub struct ArchivePlan {
+    /// The scanned folder the files come from.
+    pub folder: PathBuf,
+    /// Folder the archives are written to.
+    pub archive_folder: PathBuf,
+    pub archives: Vec<PlannedArchive>,
+    /// How the files were selected.
+    pub selection: Selection,
+    /// File names that were never archived, from the scan.
+    pub ignore_files: Vec<String>,
+}
+
+/// The builder a plan was made with, recorded in the archive manifests.
+#[derive(Debug, Clone)]
+pub enum Selection {
+    All,
+    PerType,
+    ByExtension(Vec<String>),
+    ByName(NameMatcher),
+}
+
+/// A single archive of an [`ArchivePlan`].
+#[derive(Debug, Clone)]
+pub struct PlannedArchive {
+    /// What the archive holds: `full`, a file ending or an identifier string.
+    pub label: String,
+    /// File name of the archive without the format's extension, e.g.
+    /// `csv_archive_2024_3_1_14_5`.
+    pub name: String,
+    /// Folder entries of the archive, parents before their children.
+    pub directories: Vec<String>,
+    pub files: Vec<FileEntry>,
+    /// When the plan was made, used for the name of the archive.
+    pub created: DateTime<Local>,
+}
+
+impl ArchivePlan {
+    /// All files in one archive (option 1).
+    ///
+    /// Like with the other builders, binaries that go into an archive of
+    /// their own get one archive per class.
+    pub fn all(scan: &ScanResult) -> Self {
+        let now = Local::now();
+        let mut archive = PlannedArchive::new("full", scan.files.clone(), &now);
+        //keep empty subfolders as well
+        archive.directories = scan.directories.clone();
+        let mut archives = vec![archive];
+        archives.extend(binary_archives(scan, &now, |_| true));
+
+        ArchivePlan {
+            folder: scan.folder.clone(),
+            archive_folder: scan.archive_folder(),
+            archives,
+            selection: Selection::All,
+            ignore_files: scan.ignore_files.clone(),
+        }
+    }
+
+    /// Separate archives for each file type (option 2).
+    pub fn per_type(scan: &ScanResult) -> Self {
+        let now = Local::now();
+        let mut archives: Vec<PlannedArchive> = scan
+            .file_endings
+            .iter()
+            .map(|file_ending| PlannedArchive::new(file_ending, scan.files_with_extension(file_ending).cloned().collect(), &now))
+            .collect();
+        archives.extend(binary_archives(scan, &now, |_| true));
+
+        ArchivePlan {
+            folder: scan.folder.clone(),
+            archive_folder: scan.archive_folder(),
+            archives,
+            selection: Selection::PerType,
+            ignore_files: scan.ignore_files.clone(),
+        }
+    }
+
+    /// One archive per selected file type (option 3).
+    ///
+    /// Fails if one of the file endings doesn't occur in the scan.
+    pub fn by_extension(scan: &ScanResult, file_endings: &[String]) -> Result<Self> {
+        if let Some(unknown) = file_endings.iter().find(|file_ending| !scan.file_endings.contains(file_ending)) {
+            return Err(Error::UnknownExtension(unknown.clone()));
+        }
+
+        let now = Local::now();
+        let mut archives: Vec<PlannedArchive> = file_endings
+            .iter()
+            .map(|file_ending| PlannedArchive::new(file_ending, scan.files_with_extension(file_ending).cloned().collect(), &now))
+            .collect();
+        archives.extend(binary_archives(scan, &now, |file| {
+            file.extension.as_ref().is_some_and(|extension| file_endings.contains(extension))
+        }));
+
+        Ok(ArchivePlan {
+            folder: scan.folder.clone(),
+            archive_folder: scan.archive_folder(),
+            archives,
+            selection: Selection::ByExtension(file_endings.to_vec()),
+            ignore_files: scan.ignore_files.clone(),
+        })
+    }
+
+    /// All files containing `identifier` in their name, in one archive (option 4).
+    pub fn by_name(scan: &ScanResult, identifier: &str) -> Self {
+        ArchivePlan::by_matcher(scan, &NameMatcher::contains(identifier))
+    }
+
+    /// All files whose name matches `matcher`, in one archive (option 4 with
+    /// patterns).
+    pub fn by_matcher(scan: &ScanResult, matcher: &NameMatcher) -> Self {
+        let now = Local::now();
+        let files = scan.files.iter().filter(|file| matcher.is_match(file.file_name())).cloned().collect();
+        let mut archives = vec![PlannedArchive::new(&matcher.label(), files, &now)];
+        archives.extend(binary_archives(scan, &now, |file| matcher.is_match(file.file_name())));
+
+        ArchivePlan {
+            folder: scan.folder.clone(),
+            archive_folder: scan.archive_folder(),
+            archives,
+            selection: Selection::ByName(matcher.clone()),
+            ignore_files: scan.ignore_files.clone(),
+        }
+    }
+
+    /// Writes the archives to `folder` instead of the `archive` subfolder of
+    /// the scanned folder.
+    pub fn destination(mut self, folder: impl Into<PathBuf>) -> Self {
+        self.archive_folder = folder.into();
+        self
+    }
+
+    /// Names the archives after `template` instead of the default
+    /// `{label}_archive_...` names.
+    pub fn name_template(mut self, template: &NameTemplate) -> Self {
+        let folder = self.folder.canonicalize().unwrap_or_else(|_| self.folder.clone());
+        let folder = folder.file_name().unwrap_or_default().to_string_lossy().to_string();
+        for archive in &mut self.archives {
+            archive.name = template.render(&archive.label, &folder, &archive.created);
+        }
+        self
+    }
+
+    /// Adds `_1`, `_2`, ... to the names of archives that would overwrite an
+    /// existing archive (or its volumes, or its encrypted version) in the
+    /// archive folder.
+    pub fn unique_names(mut self, format: Format) -> Self {
+        let mut taken: HashSet<String> = HashSet::new();
+        let exists = |archive: &PlannedArchive| {
+            let file_name = archive.file_name(format);
+            [file_name.clone(), format!("{}{}", file_name, format::ENCRYPTED_SUFFIX)]
+                .iter()
+                .any(|file_name| volume::volumes(&self.archive_folder.join(file_name))[0].exists())
+        };
+        for archive in &mut self.archives {
+            let name = archive.name.clone();
+            let mut counter = 0;
+            while taken.contains(&archive.name) || exists(archive) {
+                counter += 1;
+                archive.name = format!("{}_{}", name, counter);
+            }
+            taken.insert(archive.name.clone());
+        }
+        self
+    }
+
+    /// All files of the plan, over all archives.
+    pub fn files(&self) -> impl Iterator<Item = &FileEntry> {
+        self.archives.iter().flat_map(|archive| archive.files.iter())
+    }
+
+    /// Files of the scan that don't go into any archive of the plan.
+    pub fn unselected<'a>(&self, scan: &'a ScanResult) -> Vec<&'a FileEntry> {
+        let planned: HashSet<&Path> = self.files().map(|file| file.path.as_path()).collect();
+        scan.files.iter().filter(|file| !planned.contains(file.path.as_path())).collect()
+    }
+}
+
+/// One archive per binary class for the binaries of the scan that go into
+/// an archive of their own and pass `select`, named after the class.
+fn binary_archives(scan: &ScanResult, now: &DateTime<Local>, select: impl Fn(&FileEntry) -> bool) -> Vec<PlannedArchive> {
+    let mut classes: Vec<BinaryClass> = scan.own_archive.iter().map(|(class, _)| *class).collect();
+    classes.sort();
+    classes.dedup();
+
+    classes
+        .into_iter()
+        .filter_map(|class| {
+            let files: Vec<FileEntry> = scan
+                .own_archive
+                .iter()
+                .filter(|(file_class, file)| *file_class == class && select(file))
+                .map(|(_, file)| file.clone())
+                .collect();
+            if files.is_empty() {
+                None
+            } else {
+                Some(PlannedArchive::new(class.name(), files, now))
+            }
+        })
+        .collect()
+}
+
+impl PlannedArchive {
+    /// Names the archive `{label}_archive_{year}_{month}_{day}_{hour}_{minute}`.
+    fn new(label: &str, files: Vec<FileEntry>, now: &DateTime<Local>) -> Self {
+        let name = NameTemplate::default().render(label, "", now);
+
+        let mut directories: Vec<String> = Vec::new();
+        for directory in files.iter().flat_map(FileEntry::parent_directories) {
+            if !directories.iter().any(|known| known == directory) {
+                directories.push(directory.to_string());
+            }
+        }
+        directories.sort();
+
+        PlannedArchive {
+            label: label.to_string(),
+            name,
+            directories,
+            files,
+            created: *now,
+        }
+    }
+
+    /// A copy of the archive holding only `files`, with the same name.
+    pub fn with_files(&self, files: Vec<FileEntry>) -> PlannedArchive {
+        PlannedArchive {
+            name: self.name.clone(),
+            ..PlannedArchive::new(&self.label, files, &self.created)
+        }
+    }
+
+    /// File name of the archive in the given format, e.g.
+    /// `csv_archive_2024_3_1_14_5.tar.gz`.
+    pub fn file_name(&self, format: Format) -> String {
+        format!("{}.{}", self.name, format.extension())
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selection::All => write!(f, "all"),
+            Selection::PerType => write!(f, "per-type"),
+            Selection::ByExtension(_) => write!(f, "by-extension"),
+            Selection::ByName(_) => write!(f, "by-name"),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use flate2::write::DeflateEncoder;
+
+use crate::compression::Compression;
+use crate::scanner::FileEntry;
+
+/// File endings of formats that are already compressed and are stored as is
+/// by default.
+const PRECOMPRESSED_ENDINGS: &[&str] = &[
+    // images
+    "jpg", "jpeg", "png", "gif", "webp", "heic", "avif",
+    // audio and video
+    "mp3", "aac", "m4a", "ogg", "opus", "flac", "mp4", "m4v", "mkv", "mov", "avi", "webm",
+    // archives
+    "zip", "gz", "tgz", "bz2", "xz", "txz", "zst", "7z", "rar", "lz4", "jar", "apk",
+    // office documents
+    "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub",
+];
+
+/// Size of the block that is test-compressed when sampling.
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Files whose sample doesn't shrink below this ratio are stored.
+const SAMPLE_THRESHOLD: f64 = 0.95;
+
+/// Decides the compression of each file by its file ending.
+///
+/// The built-in table stores already compressed formats (images, videos,
+/// archives, ...) instead of recompressing them; all other files use the
+/// compression chosen for the run. Entries of the table can be overridden
+/// with [`set`](CompressionPolicy::set).
+#[derive(Debug, Clone)]
+pub struct CompressionPolicy {
+    by_extension: HashMap<String, Compression>,
+    sample: bool,
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        let by_extension = PRECOMPRESSED_ENDINGS
+            .iter()
+            .map(|extension| (extension.to_string(), Compression::Stored))
+            .collect();
+
+        CompressionPolicy {
+            by_extension,
+            sample: false,
+        }
+    }
+}
+
+impl CompressionPolicy {
+    /// A policy without any built-in entries.
+    pub fn empty() -> Self {
+        CompressionPolicy {
+            by_extension: HashMap::new(),
+            sample: false,
+        }
+    }
+
+    /// Uses `compression` for all files with the given file ending.
+    pub fn set(mut self, extension: &str, compression: Compression) -> Self {
+        self.by_extension.insert(extension.to_ascii_lowercase(), compression);
+        self
+    }
+
+    /// Test-compresses the first block of every file that isn't in the table
+    /// and stores the file if the block doesn't compress.
+    pub fn sample(mut self, sample: bool) -> Self {
+        self.sample = sample;
+        self
+    }
+
+    pub fn is_sampling(&self) -> bool {
+        self.sample
+    }
+
+    /// The compression from the table for `entry`, if there is one.
+    pub fn lookup(&self, entry: &FileEntry) -> Option<Compression> {
+        let extension = entry.extension.as_ref()?.to_ascii_lowercase();
+        self.by_extension.get(&extension).copied()
+    }
+}
+
+/// Reads the first block of `reader` into memory, so it can be sampled with
+/// [`compresses_well`] and then written out in front of the rest.
+pub(crate) fn read_sample(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    reader.take(SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+    Ok(sample)
+}
+
+/// Whether a fast deflate pass makes `sample` noticeably smaller.
+pub(crate) fn compresses_well(sample: &[u8]) -> bool {
+    if sample.is_empty() {
+        return true;
+    }
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+    let compressed = encoder.write_all(sample).and_then(|_| encoder.finish());
+    match compressed {
+        Ok(compressed) => (compressed.len() as f64) < sample.len() as f64 * SAMPLE_THRESHOLD,
+        Err(_) => true,
+    }
+}
+
+/// Parses a policy entry like `jpg=store` or `log=zstd:19`.
+pub fn parse_rule(input: &str) -> Result<(String, Compression), String> {
+    let (extension, compression) = input
+        .split_once('=')
+        .ok_or_else(|| format!("expected EXT=METHOD, got '{}'", input))?;
+    let extension = extension.trim().trim_start_matches('.');
+    if extension.is_empty() {
+        return Err(format!("missing file ending in '{}'", input));
+    }
+    Ok((extension.to_string(), compression.parse()?))
+}
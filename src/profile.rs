@@ -0,0 +1,464 @@
+//! Named profiles read from TOML config files, so recurring archive jobs can
+//! be run by name instead of retyping their options.
+//!
+//! ```toml
+//! [profile.logs]
+//! folder = "/var/log/app"
+//! mode = "by-extension"
+//! ext = ["log", "csv"]
+//! compression = "zstd:19"
+//! format = "tar.zst"
+//! destination = "/backup/logs"
+//! name-template = "{folder}_{label}_{date}"
+//! after = "trash"
+//! ```
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use the_archivist::{
+    binary, recipient, AgeFilter, ArchivePlan, BinaryPolicy, Compression, Format, NameMatcher, NameTemplate, Recipient, Retention,
+    ScanResult, Scanner, TimeSpec, Timestamp,
+};
+use toml::Spanned;
+
+use crate::cli::{parse_size, parse_volume_size, AfterArchive, Mode};
+
+/// Name of the config file of a folder.
+pub const FOLDER_CONFIG: &str = ".archivist.toml";
+
+/// A checked profile, ready to run.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub folder: PathBuf,
+    pub mode: Mode,
+    pub extensions: Vec<String>,
+    pub matcher: Option<NameMatcher>,
+    pub recursive: bool,
+    pub compression: Option<Compression>,
+    pub format: Option<Format>,
+    pub destination: Option<PathBuf>,
+    pub name_template: Option<NameTemplate>,
+    pub after_archive: AfterArchive,
+    pub age: Option<AgeFilter>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub volume_size: Option<u64>,
+    /// Encrypt the archives, with the password given when running.
+    pub encrypt: bool,
+    /// Public keys to encrypt the archives to, including the ones of the
+    /// recipients file.
+    pub recipients: Vec<Recipient>,
+    pub gitignore: bool,
+    pub binaries: BinaryPolicy,
+    /// Number of archives written at the same time.
+    pub jobs: Option<usize>,
+    /// Watch mode: archive the files in groups of this many.
+    pub batch: Option<usize>,
+    /// Watch mode: only archive files that haven't changed for this long.
+    pub unchanged_for: Option<Duration>,
+    /// Prune the archive folder after archiving.
+    pub retention: Option<Retention>,
+}
+
+/// A mistake in a config file.
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    /// Line and column (1-based) of the mistake, if it has a place.
+    pub position: Option<(usize, usize)>,
+    /// The profile the mistake is in.
+    pub profile: Option<String>,
+    pub message: String,
+}
+
+/// The profiles of a config file, and the mistakes found in it.
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub profiles: Vec<Profile>,
+    pub errors: Vec<ConfigError>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    profile: BTreeMap<String, RawProfile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawProfile {
+    folder: Option<String>,
+    mode: Spanned<String>,
+    #[serde(default)]
+    ext: Vec<String>,
+    #[serde(default)]
+    name: Vec<Spanned<String>>,
+    #[serde(default)]
+    exclude_name: Vec<Spanned<String>>,
+    #[serde(default)]
+    ignore_case: bool,
+    #[serde(default)]
+    recursive: bool,
+    compression: Option<Spanned<String>>,
+    format: Option<Spanned<String>>,
+    destination: Option<String>,
+    name_template: Option<Spanned<String>>,
+    after: Option<Spanned<String>>,
+    older_than: Option<Spanned<String>>,
+    newer_than: Option<Spanned<String>>,
+    time: Option<Spanned<String>>,
+    min_size: Option<Spanned<String>>,
+    max_size: Option<Spanned<String>>,
+    volume_size: Option<Spanned<String>>,
+    #[serde(default)]
+    encrypt: bool,
+    #[serde(default)]
+    recipients: Vec<Spanned<String>>,
+    recipients_file: Option<Spanned<String>>,
+    #[serde(default)]
+    gitignore: bool,
+    #[serde(default)]
+    binaries: Vec<Spanned<String>>,
+    jobs: Option<Spanned<usize>>,
+    batch: Option<Spanned<usize>>,
+    unchanged_for: Option<Spanned<String>>,
+    prune: Option<RawRetention>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawRetention {
+    keep_last: Option<usize>,
+    keep_daily: Option<usize>,
+    keep_weekly: Option<usize>,
+    keep_monthly: Option<usize>,
+    max_age: Option<Spanned<String>>,
+}
+
+/// The config files profiles are looked up in, the folder's config file in
+/// the current folder first, then the user's config file.
+pub fn config_files() -> Vec<PathBuf> {
+    let folder = env::current_dir().ok().map(|dir| dir.join(FOLDER_CONFIG));
+    folder.into_iter().chain(user_config()).filter(|path| path.is_file()).collect()
+}
+
+/// `$XDG_CONFIG_HOME/the_archivist/config.toml`, falling back to
+/// `~/.config/the_archivist/config.toml`.
+pub fn user_config() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("the_archivist").join("config.toml"))
+}
+
+impl ConfigFile {
+    /// Reads and checks a config file. Syntax errors make the whole file
+    /// invalid; other mistakes only invalidate the profile they are in.
+    pub fn load(path: &Path) -> ConfigFile {
+        let mut file = ConfigFile {
+            path: path.to_path_buf(),
+            profiles: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                file.errors.push(ConfigError {
+                    path: path.to_path_buf(),
+                    position: None,
+                    profile: None,
+                    message: err.to_string(),
+                });
+                return file;
+            }
+        };
+
+        let raw: RawConfig = match toml::from_str(&contents) {
+            Ok(raw) => raw,
+            Err(err) => {
+                file.errors.push(ConfigError {
+                    path: path.to_path_buf(),
+                    position: err.span().map(|span| position(&contents, span.start)),
+                    profile: None,
+                    message: err.message().to_string(),
+                });
+                return file;
+            }
+        };
+
+        //relative paths are relative to the config file
+        //a config file given without folders is in the current folder
+        let base = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        for (name, profile) in raw.profile {
+            let mut checker = Checker {
+                path,
+                contents: &contents,
+                profile: &name,
+                errors: Vec::new(),
+            };
+            match checker.profile(&name, profile, base) {
+                Some(profile) if checker.errors.is_empty() => file.profiles.push(profile),
+                _ => {
+                    checker.errors.sort_by_key(|err| err.position);
+                    file.errors.extend(checker.errors);
+                }
+            }
+        }
+        file
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Whether the file has a profile called `name`, valid or not.
+    pub fn defines(&self, name: &str) -> bool {
+        self.profile(name).is_some() || self.errors.iter().any(|err| err.profile.as_deref() == Some(name))
+    }
+}
+
+impl Profile {
+    /// A scanner for the folder of the profile, with its filters.
+    pub fn scanner(&self) -> Scanner {
+        let mut scanner = Scanner::new(&self.folder)
+            .recursive(self.recursive)
+            .gitignore(self.gitignore)
+            .binaries(self.binaries.clone());
+        if let Some(destination) = &self.destination {
+            scanner = scanner.archive_folder(destination);
+        }
+        if let Some(age) = self.age {
+            scanner = scanner.age(age);
+        }
+        if let Some(size) = self.min_size {
+            scanner = scanner.min_size(size);
+        }
+        if let Some(size) = self.max_size {
+            scanner = scanner.max_size(size);
+        }
+        scanner
+    }
+
+    /// The archives of the profile for `scan`, written to the profile's
+    /// destination. File endings of the profile that aren't in the scan are
+    /// left out; `None` if none of them is.
+    pub fn plan(&self, scan: &ScanResult) -> Option<ArchivePlan> {
+        let mut plan = match self.mode {
+            Mode::All => ArchivePlan::all(scan),
+            Mode::PerType => ArchivePlan::per_type(scan),
+            Mode::ByExtension => {
+                let present: Vec<String> =
+                    self.extensions.iter().filter(|ext| scan.file_endings.contains(ext)).cloned().collect();
+                if present.is_empty() {
+                    return None;
+                }
+                ArchivePlan::by_extension(scan, &present).ok()?
+            }
+            Mode::ByName => ArchivePlan::by_matcher(scan, self.matcher.as_ref()?),
+        };
+        if let Some(destination) = &self.destination {
+            plan = plan.destination(destination);
+        }
+        if let Some(template) = &self.name_template {
+            plan = plan.name_template(template);
+        }
+        Some(plan)
+    }
+}
+
+/// Collects the mistakes of one profile.
+struct Checker<'a> {
+    path: &'a Path,
+    contents: &'a str,
+    profile: &'a str,
+    errors: Vec<ConfigError>,
+}
+
+impl Checker<'_> {
+    fn profile(&mut self, name: &str, raw: RawProfile, base: &Path) -> Option<Profile> {
+        let mode = self.parse(&raw.mode, |mode| {
+            Mode::from_str(mode, true).map_err(|_| {
+                format!("unknown mode '{}' (expected all, per-type, by-extension or by-name)", mode)
+            })
+        });
+        if mode == Some(Mode::ByExtension) && raw.ext.is_empty() {
+            self.error(raw.mode.span(), "by-extension mode needs the file endings in 'ext'");
+        }
+        if mode == Some(Mode::ByName) && raw.name.is_empty() {
+            self.error(raw.mode.span(), "by-name mode needs at least one pattern in 'name'");
+        }
+
+        //check the patterns one by one, so the mistake can be pointed at
+        for pattern in raw.name.iter().chain(&raw.exclude_name) {
+            self.parse(pattern, |pattern| {
+                NameMatcher::new(&[pattern.to_string()], &[], raw.ignore_case).map_err(|err| err.to_string())
+            });
+        }
+        let matcher = if raw.name.is_empty() {
+            None
+        } else {
+            let include: Vec<String> = raw.name.iter().map(|pattern| pattern.get_ref().clone()).collect();
+            let exclude: Vec<String> = raw.exclude_name.iter().map(|pattern| pattern.get_ref().clone()).collect();
+            NameMatcher::new(&include, &exclude, raw.ignore_case).ok()
+        };
+
+        let compression = raw.compression.as_ref().and_then(|compression| self.parse(compression, str::parse));
+        let format = raw.format.as_ref().and_then(|format| self.parse(format, str::parse));
+        let name_template = raw.name_template.as_ref().and_then(|template| self.parse(template, str::parse));
+        let after_archive = raw.after.as_ref().and_then(|after| self.parse(after, parse_after));
+        let older_than = raw.older_than.as_ref().and_then(|time| self.parse(time, str::parse::<TimeSpec>));
+        let newer_than = raw.newer_than.as_ref().and_then(|time| self.parse(time, str::parse::<TimeSpec>));
+        let time = raw.time.as_ref().and_then(|time| self.parse(time, str::parse::<Timestamp>));
+        let min_size = raw.min_size.as_ref().and_then(|size| self.parse(size, parse_size));
+        let max_size = raw.max_size.as_ref().and_then(|size| self.parse(size, parse_size));
+        let volume_size = raw.volume_size.as_ref().and_then(|size| self.parse(size, parse_volume_size));
+
+        if let Some(jobs) = raw.jobs.as_ref().filter(|jobs| *jobs.get_ref() == 0) {
+            self.error(jobs.span(), "jobs has to be at least 1");
+        }
+        if let Some(batch) = raw.batch.as_ref().filter(|batch| *batch.get_ref() == 0) {
+            self.error(batch.span(), "batch has to be at least 1");
+        }
+        let unchanged_for = raw.unchanged_for.as_ref().and_then(|time| {
+            self.parse(time, |time| match time.parse::<TimeSpec>()? {
+                TimeSpec::Ago(duration) => Ok(duration),
+                TimeSpec::Date(_) => Err(format!("invalid duration '{}' (expected e.g. 30s or 10m)", time)),
+            })
+        });
+
+        let retention = raw.prune.as_ref().map(|prune| {
+            let mut retention = Retention::new();
+            if let Some(count) = prune.keep_last {
+                retention = retention.keep_last(count);
+            }
+            if let Some(count) = prune.keep_daily {
+                retention = retention.keep_daily(count);
+            }
+            if let Some(count) = prune.keep_weekly {
+                retention = retention.keep_weekly(count);
+            }
+            if let Some(count) = prune.keep_monthly {
+                retention = retention.keep_monthly(count);
+            }
+            if let Some(age) = prune.max_age.as_ref().and_then(|age| self.parse(age, str::parse::<TimeSpec>)) {
+                retention = retention.max_age(age);
+            }
+            retention
+        });
+
+        let mut recipients: Vec<Recipient> = raw.recipients.iter().filter_map(|key| self.parse(key, str::parse)).collect();
+        if let Some(file) = &raw.recipients_file {
+            let file_recipients = self.parse(file, |file| recipient::read_recipients(&base.join(file)).map_err(|err| err.to_string()));
+            recipients.extend(file_recipients.unwrap_or_default());
+        }
+
+        let mut binaries = BinaryPolicy::new();
+        for rule in &raw.binaries {
+            if let Some((class, action)) = self.parse(rule, binary::parse_rule) {
+                binaries = binaries.set(class, action);
+            }
+        }
+
+        let age = if older_than.is_some() || newer_than.is_some() {
+            let mut filter = AgeFilter::new().timestamp(time.unwrap_or_default());
+            if let Some(time) = older_than {
+                filter = filter.older_than(time);
+            }
+            if let Some(time) = newer_than {
+                filter = filter.newer_than(time);
+            }
+            Some(filter)
+        } else {
+            None
+        };
+
+        Some(Profile {
+            name: name.to_string(),
+            folder: raw.folder.map_or_else(|| base.to_path_buf(), |folder| base.join(folder)),
+            mode: mode?,
+            extensions: raw.ext,
+            matcher,
+            recursive: raw.recursive,
+            compression,
+            format,
+            destination: raw.destination.map(|destination| base.join(destination)),
+            name_template,
+            after_archive: after_archive.unwrap_or(AfterArchive::Keep),
+            age,
+            min_size,
+            max_size,
+            volume_size,
+            encrypt: raw.encrypt,
+            recipients,
+            gitignore: raw.gitignore,
+            binaries,
+            jobs: raw.jobs.map(Spanned::into_inner),
+            batch: raw.batch.map(Spanned::into_inner),
+            unchanged_for,
+            retention: retention.filter(|retention| !retention.is_empty()),
+        })
+    }
+
+    /// Parses a value, recording the error at the value's place.
+    fn parse<T>(&mut self, value: &Spanned<String>, parse: impl FnOnce(&str) -> Result<T, String>) -> Option<T> {
+        match parse(value.get_ref()) {
+            Ok(parsed) => Some(parsed),
+            Err(message) => {
+                self.error(value.span(), &message);
+                None
+            }
+        }
+    }
+
+    fn error(&mut self, span: Range<usize>, message: &str) {
+        self.errors.push(ConfigError {
+            path: self.path.to_path_buf(),
+            position: Some(position(self.contents, span.start)),
+            profile: Some(self.profile.to_string()),
+            message: message.to_string(),
+        });
+    }
+}
+
+fn parse_after(input: &str) -> Result<AfterArchive, String> {
+    match input.trim().to_ascii_lowercase().as_str() {
+        "keep" => Ok(AfterArchive::Keep),
+        "trash" => Ok(AfterArchive::Trash),
+        "delete" => Ok(AfterArchive::Delete),
+        _ => Err(format!("unknown action '{}' (expected keep, trash or delete)", input)),
+    }
+}
+
+/// Line and column (1-based) of a byte offset.
+fn position(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rfind('\n').map_or(before.len(), |newline| before.len() - newline - 1) + 1;
+    (line, column)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some((line, column)) = self.position {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        if let Some(profile) = &self.profile {
+            write!(f, ": profile '{}'", profile)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
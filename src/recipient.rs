@@ -0,0 +1,236 @@
+//! Archives encrypted to public keys, in the [age](https://age-encryption.org/v1)
+//! format.
+//!
+//! An archive is encrypted to one or more [`Recipient`]s (`age1...`) and can
+//! only be read with one of their [`Identity`] files (`AGE-SECRET-KEY-1...`).
+//! The machine writing the archives only needs the public keys, so it can't
+//! read the archives it wrote. The encrypted archives can also be decrypted
+//! with `age -d -i key.txt name.zip.age > name.zip`.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use age::secrecy::ExposeSecret;
+use age::DecryptError;
+use chrono::Local;
+use zeroize::Zeroizing;
+
+use crate::error::{Error, Result};
+
+/// Public key an archive is encrypted to, written as `age1...`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Recipient(age::x25519::Recipient);
+
+impl fmt::Display for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Debug for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recipient({})", self)
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        input
+            .trim()
+            .parse()
+            .map(Recipient)
+            .map_err(|_| format!("'{}' is not a public key (expected age1...)", input))
+    }
+}
+
+/// Private key that decrypts the archives encrypted to its [`Recipient`].
+/// It is wiped from memory when dropped and never shown in debug output.
+#[derive(Clone)]
+pub struct Identity(age::x25519::Identity);
+
+impl Identity {
+    /// Creates a new random identity.
+    pub fn generate() -> Self {
+        Identity(age::x25519::Identity::generate())
+    }
+
+    /// The public key archives are encrypted to for this identity.
+    pub fn to_public(&self) -> Recipient {
+        Recipient(self.0.to_public())
+    }
+
+    /// The identity as written in identity files, `AGE-SECRET-KEY-1...`.
+    pub fn to_secret_key(&self) -> Zeroizing<String> {
+        Zeroizing::new(self.0.to_string().expose_secret().to_string())
+    }
+
+    /// The contents of a new identity file, in the format of `age-keygen`.
+    pub fn to_file_contents(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!(
+            "# created: {}\n# public key: {}\n{}\n",
+            Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            self.to_public(),
+            self.to_secret_key().as_str()
+        ))
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Identity({})", self.to_public())
+    }
+}
+
+impl FromStr for Identity {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        input
+            .trim()
+            .parse()
+            .map(Identity)
+            //the input is a secret, so it isn't repeated in the message
+            .map_err(|_| "not a secret key (expected AGE-SECRET-KEY-1...)".to_string())
+    }
+}
+
+/// Reads the identities of an identity file, as written by `age-keygen` or
+/// the `keygen` command: one `AGE-SECRET-KEY-1...` per line, with `#`
+/// comments and blank lines.
+pub fn read_identities(path: &Path) -> Result<Vec<Identity>> {
+    let contents = Zeroizing::new(fs::read_to_string(path).map_err(|err| Error::io(path, err))?);
+    let identities = read_lines(path, &contents)?;
+    if identities.is_empty() {
+        return Err(Error::io(path, invalid("no secret keys in the identity file")));
+    }
+    Ok(identities)
+}
+
+/// Reads the public keys of a recipients file: one `age1...` per line, with
+/// `#` comments and blank lines.
+pub fn read_recipients(path: &Path) -> Result<Vec<Recipient>> {
+    let contents = fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    read_lines(path, &contents)
+}
+
+fn read_lines<T: FromStr<Err = String>>(path: &Path, contents: &str) -> Result<Vec<T>> {
+    let mut keys = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let key = line
+            .parse()
+            .map_err(|err| Error::io(path, invalid(&format!("line {}: {}", index + 1, err))))?;
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// Encrypts everything `input` returns to `recipients` and writes it to
+/// `output`.
+pub(crate) fn encrypt(mut input: impl Read, output: impl Write, recipients: &[Recipient]) -> io::Result<()> {
+    let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|recipient| &recipient.0 as &dyn age::Recipient))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    let mut writer = encryptor.wrap_output(output)?;
+    io::copy(&mut input, &mut writer)?;
+    writer.finish()?.flush()
+}
+
+/// Decrypts the encrypted archive `input` while it is read, using whichever
+/// of `identities` it was encrypted to. Every chunk read is authenticated,
+/// so a changed archive fails to read.
+pub(crate) fn decrypt<R: Read + Seek>(input: R, archive: &Path, identities: &[Identity]) -> Result<impl Read + Seek> {
+    if identities.is_empty() {
+        return Err(Error::IdentityRequired(archive.to_path_buf()));
+    }
+    let decryptor = age::Decryptor::new_buffered(io::BufReader::new(input)).map_err(|err| decrypt_error(archive, err))?;
+    decryptor
+        .decrypt(identities.iter().map(|identity| &identity.0 as &dyn age::Identity))
+        .map_err(|err| decrypt_error(archive, err))
+}
+
+fn decrypt_error(archive: &Path, err: DecryptError) -> Error {
+    match err {
+        DecryptError::NoMatchingKeys => Error::NoMatchingIdentity(archive.to_path_buf()),
+        err => Error::io(archive, invalid(&err.to_string())),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn encrypted(contents: &[u8], recipients: &[Recipient]) -> Cursor<Vec<u8>> {
+        let mut file = Vec::new();
+        encrypt(contents, &mut file, recipients).unwrap();
+        Cursor::new(file)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (first, second) = (Identity::generate(), Identity::generate());
+        let contents: Vec<u8> = (0..200_000u32).map(|byte| byte as u8).collect();
+        let file = encrypted(&contents, &[first.to_public(), second.to_public()]);
+
+        let mut decrypted = decrypt(file, Path::new("a.zip.age"), &[second]).unwrap();
+        let mut output = Vec::new();
+        decrypted.read_to_end(&mut output).unwrap();
+        assert_eq!(output, contents);
+    }
+
+    #[test]
+    fn wrong_identity() {
+        let file = encrypted(b"secret", &[Identity::generate().to_public()]);
+        let decrypted = decrypt(file, Path::new("a.zip.age"), &[Identity::generate()]);
+        assert!(matches!(decrypted, Err(Error::NoMatchingIdentity(_))));
+    }
+
+    #[test]
+    fn changed_payload() {
+        let identity = Identity::generate();
+        let mut file = encrypted(b"secret contents", &[identity.to_public()]);
+        *file.get_mut().last_mut().unwrap() ^= 1;
+        let mut output = Vec::new();
+        let read = decrypt(file, Path::new("a.zip.age"), &[identity]).and_then(|mut reader| {
+            reader.read_to_end(&mut output).map_err(|err| Error::io(Path::new("a.zip.age"), err))
+        });
+        assert!(read.is_err());
+    }
+
+    #[test]
+    fn parse_keys() {
+        //from the age specification test vectors
+        let identity: Identity = "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX".parse().unwrap();
+        assert_eq!(
+            identity.to_public().to_string(),
+            "age1zvkyg2lqzraa2lnjvqej32nkuu0ues2s82hzrye869xeexvn73equnujwj"
+        );
+        assert_eq!(identity.to_secret_key().as_str(), "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX");
+
+        let recipient: Recipient = " age1zvkyg2lqzraa2lnjvqej32nkuu0ues2s82hzrye869xeexvn73equnujwj ".parse().unwrap();
+        assert_eq!(recipient, identity.to_public());
+        assert!("age1notakey".parse::<Recipient>().is_err());
+        assert!("AGE-SECRET-KEY-1".parse::<Identity>().is_err());
+    }
+
+    #[test]
+    fn read_key_lines() {
+        let contents = "# created: now\n\nage1zvkyg2lqzraa2lnjvqej32nkuu0ues2s82hzrye869xeexvn73equnujwj\n";
+        let recipients: Vec<Recipient> = read_lines(Path::new("keys.txt"), contents).unwrap();
+        assert_eq!(recipients.len(), 1);
+        assert!(read_lines::<Recipient>(Path::new("keys.txt"), "age1 broken").is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use crate::compression::Compression;
+use crate::error::{Error, Result};
+use crate::format::Format;
+use crate::manifest::Manifest;
+use crate::password::Password;
+use crate::recipient::Recipient;
+use crate::scanner::FileEntry;
+use crate::verify::{self, Verification};
+
+/// Outcome of [`Archiver::execute`](crate::Archiver::execute).
+#[derive(Debug, Default)]
+pub struct ArchiveReport {
+    /// The outcome of every archive of the plan, in the order of the plan.
+    pub archives: Vec<Result<ArchiveResult>>,
+}
+
+/// Outcome of writing a single archive.
+#[derive(Debug, Clone)]
+pub struct ArchiveResult {
+    /// Path of the written archive.
+    pub path: PathBuf,
+    pub format: Format,
+    /// Manifest of the archive: the files that were successfully written
+    /// into it. Nothing else is ever deleted after archiving.
+    pub files: Vec<FileEntry>,
+    /// Files that could not be archived, with the reason.
+    pub errors: Vec<(PathBuf, String)>,
+    /// Compression used for the entries.
+    pub compression: Compression,
+    /// Number of files that were stored uncompressed because of the
+    /// compression policy.
+    pub stored_files: usize,
+    /// Total size of the archived files in bytes.
+    pub original_size: u64,
+    /// Size of the written archive in bytes, of all volumes together.
+    pub archive_size: u64,
+    /// The volumes of a split archive, in order. Empty for archives that
+    /// aren't split.
+    pub volumes: Vec<PathBuf>,
+    /// Password the entries are encrypted with, needed to read them back.
+    pub password: Option<Password>,
+    /// Public keys the archive is encrypted to. Empty for archives that
+    /// aren't.
+    pub recipients: Vec<Recipient>,
+    /// Result of [`verify`](ArchiveResult::verify), if it was run.
+    pub verification: Option<Verification>,
+    /// The manifest written into the archive.
+    pub manifest: Manifest,
+}
+
+impl ArchiveReport {
+    /// Whether every archive was written with every planned file.
+    pub fn is_complete(&self) -> bool {
+        self.archives
+            .iter()
+            .all(|archive| archive.as_ref().is_ok_and(|archive| archive.errors.is_empty()))
+    }
+
+    /// The archives that were written.
+    pub fn written(&self) -> impl Iterator<Item = &ArchiveResult> {
+        self.archives.iter().flatten()
+    }
+
+    /// The errors of the archives that failed.
+    pub fn failed(&self) -> impl Iterator<Item = &Error> {
+        self.archives.iter().filter_map(|archive| archive.as_ref().err())
+    }
+
+    /// Verifies every written archive of the report.
+    pub fn verify(&mut self) {
+        for archive in self.archives.iter_mut().flatten() {
+            archive.verify();
+        }
+    }
+
+    /// The manifests of all written archives: every file that was archived.
+    pub fn manifest(&self) -> impl Iterator<Item = &FileEntry> {
+        self.written().flat_map(|archive| archive.files.iter())
+    }
+}
+
+impl ArchiveResult {
+    /// Reopens the archive and checks it against the archived files, see
+    /// [`verify_archive`](crate::verify_archive).
+    ///
+    /// Archives encrypted to recipients can't be read back and keep the
+    /// verification done before they were encrypted.
+    pub fn verify(&mut self) -> &Verification {
+        if self.has_recipients() && self.verification.is_some() {
+            return self.verification.get_or_insert_with(Verification::default);
+        }
+        self.verification.insert(verify::verify_archive(self))
+    }
+
+    /// Whether the entries are encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.password.is_some()
+    }
+
+    /// Whether the archive is encrypted to recipients.
+    pub fn has_recipients(&self) -> bool {
+        !self.recipients.is_empty()
+    }
+
+    /// Size of the archive relative to the archived files, e.g. `0.25` when
+    /// the archive is a quarter of the original size.
+    pub fn compression_ratio(&self) -> f64 {
+        if self.original_size == 0 {
+            return 1.0;
+        }
+        self.archive_size as f64 / self.original_size as f64
+    }
+}
@@ -0,0 +1,593 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+use filetime::FileTime;
+use zip::ZipArchive;
+
+use crate::error::{Error, Result};
+use crate::format::{self, Format};
+use crate::manifest::{self, HashingReader, Manifest};
+use crate::password::{self, Password};
+use crate::recipient::{self, Identity};
+use crate::scanner::ARCHIVE_FOLDER;
+use crate::volume;
+
+/// What to do when a restored file already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conflict {
+    /// Keep the existing file.
+    #[default]
+    Skip,
+    /// Replace the existing file.
+    Overwrite,
+    /// Restore next to the existing file under a new name, e.g. `report (1).csv`.
+    Rename,
+    /// Replace the existing file only if the archived one is newer.
+    KeepNewer,
+}
+
+/// What happened to a single entry of a restored archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestoreOutcome {
+    Restored(PathBuf),
+    Overwritten(PathBuf),
+    /// Restored under a new name because the file already existed.
+    Renamed(PathBuf),
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct RestoredEntry {
+    /// Name of the entry in the archive.
+    pub name: String,
+    pub outcome: RestoreOutcome,
+    /// SHA-256 of the restored contents, for files that were written.
+    pub sha256: Option<String>,
+}
+
+/// Outcome of [`Restorer::restore`].
+#[derive(Debug, Clone)]
+pub struct RestoreReport {
+    /// Folder the archive was extracted into.
+    pub target: PathBuf,
+    pub entries: Vec<RestoredEntry>,
+    /// The manifest of the archive, if it has one.
+    pub manifest: Option<Manifest>,
+}
+
+/// Extracts archives written by the archivist back into a folder.
+///
+/// Modification times and unix permissions are restored, without the setuid,
+/// setgid and sticky bits unless [`Restorer::special_bits`] is set. Entries
+/// whose name would end up outside the target folder (absolute paths or
+/// `..`) are skipped. Restored files are checked against the SHA-256 in the archive's
+/// manifest before they replace an existing file.
+#[derive(Debug, Clone, Default)]
+pub struct Restorer {
+    target: Option<PathBuf>,
+    conflict: Conflict,
+    password: Option<Password>,
+    identities: Vec<Identity>,
+    special_bits: bool,
+}
+
+/// An archive entry, independent of the archive format.
+struct EntryInfo {
+    name: String,
+    is_dir: bool,
+    modified: Option<SystemTime>,
+    mode: Option<u32>,
+}
+
+impl Restorer {
+    pub fn new() -> Self {
+        Restorer::default()
+    }
+
+    /// Extracts into `target` instead of the folder the archive was made from.
+    pub fn target(mut self, target: impl Into<PathBuf>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Sets what happens to files that already exist (skip by default).
+    pub fn conflict(mut self, conflict: Conflict) -> Self {
+        self.conflict = conflict;
+        self
+    }
+
+    /// Sets the password of encrypted zip archives.
+    pub fn password(mut self, password: Password) -> Self {
+        self.password = Some(password);
+        self
+    }
+
+    /// Sets the identities that decrypt archives encrypted to public keys,
+    /// see [`recipient`](crate::recipient).
+    pub fn identities(mut self, identities: Vec<Identity>) -> Self {
+        self.identities = identities;
+        self
+    }
+
+    /// Also restores the setuid, setgid and sticky bits of files, which are
+    /// dropped by default.
+    pub fn special_bits(mut self, special_bits: bool) -> Self {
+        self.special_bits = special_bits;
+        self
+    }
+
+    /// Extracts `archive`. Without a target, the archive is extracted into
+    /// the folder above the `archive/` folder it is in, or else into the
+    /// folder recorded in its manifest.
+    ///
+    /// Split archives are read from all their volumes; `archive` may name any
+    /// of them. Encrypted archives fail with [`Error::PasswordRequired`] or
+    /// [`Error::WrongPassword`] before anything is extracted, archives
+    /// encrypted to public keys with [`Error::IdentityRequired`] or
+    /// [`Error::NoMatchingIdentity`].
+    pub fn restore(&self, archive: &Path) -> Result<RestoreReport> {
+        let archive = &volume::archive_of(archive);
+        let format = Format::from_path(archive).ok_or_else(|| Error::UnknownFormat(archive.to_path_buf()))?;
+        //the manifest comes after the files, so it is read first to check
+        //every file before it replaces an existing one
+        let manifest = manifest::read_from(archive, format, self.open(archive)?, self.password.as_ref())?;
+        let file = self.open(archive)?;
+        let target = match &self.target {
+            Some(target) => target.clone(),
+            None => source_folder(archive)
+                .or_else(|| manifest.as_ref().map(|manifest| manifest.folder.clone()))
+                .ok_or_else(|| Error::NoRestoreTarget(archive.to_path_buf()))?,
+        };
+        fs::create_dir_all(&target).map_err(|err| Error::io(&target, err))?;
+        let root = target.canonicalize().map_err(|err| Error::io(&target, err))?;
+
+        let mut report = RestoreReport {
+            target,
+            entries: Vec::new(),
+            manifest: None,
+        };
+        if format.is_tar() {
+            self.restore_tar(format, file, &root, manifest.as_ref(), &mut report)
+                .map_err(|err| Error::io(archive, err))?;
+        } else {
+            let mut zip = ZipArchive::new(file).map_err(|err| Error::zip(archive, err))?;
+            password::check(&mut zip, archive, self.password.as_ref())?;
+            self.restore_zip(zip, &root, manifest.as_ref(), &mut report)
+                .map_err(|err| Error::io(archive, err))?;
+        }
+        report.manifest = manifest;
+        Ok(report)
+    }
+
+    /// Opens `archive`, decrypted if it is encrypted to public keys.
+    fn open(&self, archive: &Path) -> Result<Box<dyn ArchiveReader>> {
+        let file = volume::open(archive).map_err(|err| Error::io(archive, err))?;
+        if format::is_encrypted(archive) {
+            Ok(Box::new(recipient::decrypt(file, archive, &self.identities)?))
+        } else {
+            Ok(Box::new(file))
+        }
+    }
+
+    fn restore_zip(
+        &self,
+        mut zip: ZipArchive<Box<dyn ArchiveReader>>,
+        root: &Path,
+        manifest: Option<&Manifest>,
+        report: &mut RestoreReport,
+    ) -> io::Result<()> {
+        for index in 0..zip.len() {
+            let entry = match &self.password {
+                Some(password) => zip.by_index_decrypt(index, password.as_bytes()),
+                None => zip.by_index(index),
+            };
+            let mut entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    report.entries.push(RestoredEntry {
+                        name: format!("#{}", index),
+                        outcome: RestoreOutcome::Failed(err.to_string()),
+                        sha256: None,
+                    });
+                    continue;
+                }
+            };
+            if is_metadata(entry.name()) {
+                continue;
+            }
+
+            let info = EntryInfo {
+                name: entry.name().to_string(),
+                is_dir: entry.is_dir(),
+                modified: entry
+                    .last_modified()
+                    .and_then(|modified| NaiveDateTime::try_from(modified).ok())
+                    .and_then(|modified| Local.from_local_datetime(&modified).earliest())
+                    .map(SystemTime::from),
+                mode: entry.unix_mode(),
+            };
+            let (outcome, sha256) = self.restore_entry(root, &info, manifest, &mut entry);
+            report.entries.push(RestoredEntry {
+                name: info.name,
+                outcome,
+                sha256,
+            });
+        }
+        Ok(())
+    }
+
+    fn restore_tar(
+        &self,
+        format: Format,
+        file: Box<dyn ArchiveReader>,
+        root: &Path,
+        manifest: Option<&Manifest>,
+        report: &mut RestoreReport,
+    ) -> io::Result<()> {
+        let mut tar = tar::Archive::new(format::tar_reader(format, file)?);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+            if is_metadata(&name) {
+                continue;
+            }
+
+            let header = entry.header();
+            let info = EntryInfo {
+                name,
+                is_dir: header.entry_type().is_dir(),
+                modified: header.mtime().ok().map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime)),
+                mode: header.mode().ok(),
+            };
+
+            let (outcome, sha256) = if info.is_dir || header.entry_type().is_file() {
+                self.restore_entry(root, &info, manifest, &mut entry)
+            } else {
+                (RestoreOutcome::Skipped("not a regular file or folder".to_string()), None)
+            };
+            report.entries.push(RestoredEntry {
+                name: info.name,
+                outcome,
+                sha256,
+            });
+        }
+        Ok(())
+    }
+
+    /// Restores one entry; returns the SHA-256 of the contents if a file was
+    /// written. Files listed in the manifest are only written if their
+    /// contents match it; files missing from it couldn't be read completely
+    /// when the archive was written and are skipped.
+    fn restore_entry(
+        &self,
+        root: &Path,
+        info: &EntryInfo,
+        manifest: Option<&Manifest>,
+        data: &mut dyn Read,
+    ) -> (RestoreOutcome, Option<String>) {
+        if !info.is_dir && manifest.is_some_and(|manifest| manifest.file(&info.name).is_none()) {
+            return (RestoreOutcome::Skipped("not listed in the manifest".to_string()), None);
+        }
+        let outcome = match self.destination(root, info) {
+            Ok(outcome) => outcome,
+            Err(outcome) => return (outcome, None),
+        };
+        let path = match &outcome {
+            RestoreOutcome::Restored(path) | RestoreOutcome::Overwritten(path) | RestoreOutcome::Renamed(path) => path,
+            _ => unreachable!(),
+        };
+        let expected = manifest.and_then(|manifest| manifest.file(&info.name)).map(|file| file.sha256.as_str());
+        let mode_mask = if self.special_bits { 0o7777 } else { 0o777 };
+        match write_file(path, info, data, expected, mode_mask) {
+            Ok(sha256) => (outcome, Some(sha256)),
+            Err(err) => (RestoreOutcome::Failed(err.to_string()), None),
+        }
+    }
+
+    /// Where a file entry goes, as the outcome it will have once written, or
+    /// the final outcome if nothing has to be written.
+    fn destination(&self, root: &Path, info: &EntryInfo) -> std::result::Result<RestoreOutcome, RestoreOutcome> {
+        let relative = safe_path(&info.name).ok_or_else(|| RestoreOutcome::Skipped("path leaves the target folder".to_string()))?;
+        let destination = root.join(&relative);
+
+        if info.is_dir {
+            return match fs::create_dir_all(&destination) {
+                Ok(()) => Err(RestoreOutcome::Restored(destination)),
+                Err(err) => Err(RestoreOutcome::Failed(err.to_string())),
+            };
+        }
+
+        // the name is safe, but a symlinked folder could still lead outside
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).map_err(|err| RestoreOutcome::Failed(err.to_string()))?;
+            match parent.canonicalize() {
+                Ok(parent) if parent.starts_with(root) => {}
+                Ok(_) => return Err(RestoreOutcome::Skipped("path leaves the target folder".to_string())),
+                Err(err) => return Err(RestoreOutcome::Failed(err.to_string())),
+            }
+        }
+
+        let existing = match fs::symlink_metadata(&destination) {
+            Ok(existing) => existing,
+            Err(_) => return Ok(RestoreOutcome::Restored(destination)),
+        };
+        if existing.is_dir() {
+            return Err(RestoreOutcome::Skipped("a folder with that name exists".to_string()));
+        }
+        match self.conflict {
+            Conflict::Skip => Err(RestoreOutcome::Skipped("file exists".to_string())),
+            Conflict::Overwrite => Ok(RestoreOutcome::Overwritten(destination)),
+            Conflict::Rename => Ok(RestoreOutcome::Renamed(free_name(&destination))),
+            Conflict::KeepNewer => match (info.modified, existing.modified().ok()) {
+                (Some(archived), Some(existing)) if archived > existing => Ok(RestoreOutcome::Overwritten(destination)),
+                _ => Err(RestoreOutcome::Skipped("existing file is newer".to_string())),
+            },
+        }
+    }
+}
+
+/// The folder an archive was made from: the folder above the `archive/`
+/// folder the archive is in.
+pub fn source_folder(archive: &Path) -> Option<PathBuf> {
+    let archive = archive.canonicalize().ok()?;
+    let folder = archive.parent()?;
+    if folder.file_name()? != ARCHIVE_FOLDER {
+        return None;
+    }
+    folder.parent().map(Path::to_path_buf)
+}
+
+/// Entries the archivist keeps its own data in, like the manifest.
+fn is_metadata(name: &str) -> bool {
+    name.trim_end_matches('/') == ".archivist" || name.starts_with(".archivist/")
+}
+
+/// Turns an entry name into a relative path, or `None` if the name is
+/// absolute or contains `..`.
+fn safe_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(&name.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// `report (1).csv`, `report (2).csv`, ... whichever doesn't exist yet.
+fn free_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    let mut counter = 1;
+    loop {
+        let candidate = path.with_file_name(format!("{} ({}){}", stem, counter, extension));
+        if fs::symlink_metadata(&candidate).is_err() {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+/// Writes the entry to a temporary file next to `path` first, so an existing
+/// file is only replaced by a complete one whose SHA-256 is `expected`, if
+/// given. Only the permission bits in `mode_mask` are restored. Returns the
+/// SHA-256 of the contents.
+fn write_file(path: &Path, info: &EntryInfo, data: &mut dyn Read, expected: Option<&str>, mode_mask: u32) -> io::Result<String> {
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let partial = path.with_file_name(format!(".{}.archivist-part", file_name));
+
+    //a leftover temporary file is removed, and a symlink planted in its
+    //place is never followed
+    match fs::remove_file(&partial) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let result = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&partial)?;
+        let mut reader = HashingReader::new(data);
+        io::copy(&mut reader, &mut file)?;
+        drop(file);
+        let sha256 = reader.sha256();
+        if expected.is_some_and(|expected| expected != sha256) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "contents differ from the SHA-256 in the manifest"));
+        }
+
+        #[cfg(unix)]
+        if let Some(mode) = info.mode {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&partial, fs::Permissions::from_mode(mode & mode_mask))?;
+        }
+
+        fs::rename(&partial, path)?;
+        if let Some(modified) = info.modified {
+            filetime::set_file_mtime(path, FileTime::from_system_time(modified))?;
+        }
+        Ok(sha256)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::Skip => write!(f, "skip"),
+            Conflict::Overwrite => write!(f, "overwrite"),
+            Conflict::Rename => write!(f, "rename"),
+            Conflict::KeepNewer => write!(f, "keep-newer"),
+        }
+    }
+}
+
+impl FromStr for Conflict {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "skip" => Ok(Conflict::Skip),
+            "overwrite" => Ok(Conflict::Overwrite),
+            "rename" => Ok(Conflict::Rename),
+            "keep-newer" => Ok(Conflict::KeepNewer),
+            _ => Err(format!("unknown conflict policy '{}' (expected skip, overwrite, rename or keep-newer)", input)),
+        }
+    }
+}
+
+/// An archive opened for restoring, decrypted while it is read if needed.
+trait ArchiveReader: Read + Seek {}
+
+impl<R: Read + Seek> ArchiveReader for R {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use sha2::{Digest, Sha256};
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+    use crate::manifest::{ManifestEntry, MANIFEST_NAME};
+
+    /// Writes a zip with the files and a manifest listing them with the
+    /// SHA-256 of `listed`.
+    fn write_archive(path: &Path, files: &[(&str, &[u8], u32)], listed: &[u8]) {
+        let mut zip = ZipWriter::new(fs::File::create(path).unwrap());
+        let mut manifest = Manifest {
+            version: crate::VERSION.to_string(),
+            hostname: "host".to_string(),
+            created: Local::now().to_rfc3339(),
+            folder: PathBuf::from("/data"),
+            mode: "all".to_string(),
+            label: Some("full".to_string()),
+            extension: None,
+            identifier: None,
+            ignore: Vec::new(),
+            files: Vec::new(),
+        };
+        for &(name, contents, mode) in files {
+            zip.start_file(name, SimpleFileOptions::default().unix_permissions(mode)).unwrap();
+            zip.write_all(contents).unwrap();
+            manifest.files.push(ManifestEntry {
+                name: name.to_string(),
+                path: Path::new("/data").join(name),
+                size: contents.len() as u64,
+                modified: None,
+                permissions: Some(mode),
+                sha256: hex::encode(Sha256::digest(listed)),
+            });
+        }
+        zip.start_file(MANIFEST_NAME, SimpleFileOptions::default()).unwrap();
+        zip.write_all(&manifest.to_json()).unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn changed_contents_never_replace_files() {
+        let folder = tempfile::tempdir().unwrap();
+        let archive = folder.path().join("full.zip");
+        write_archive(&archive, &[("a.txt", b"tampered", 0o644)], b"original");
+        let target = folder.path().join("target");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("a.txt"), "existing").unwrap();
+
+        let report = Restorer::new().target(&target).conflict(Conflict::Overwrite).restore(&archive).unwrap();
+        assert!(matches!(report.entries[0].outcome, RestoreOutcome::Failed(_)));
+        assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "existing");
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 1);
+        assert!(report.manifest.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn never_follows_planted_links() {
+        let folder = tempfile::tempdir().unwrap();
+        let archive = folder.path().join("full.zip");
+        write_archive(&archive, &[("a.txt", b"archived", 0o644)], b"archived");
+        let target = folder.path().join("target");
+        fs::create_dir(&target).unwrap();
+        let outside = folder.path().join("outside.txt");
+        fs::write(&outside, "outside").unwrap();
+        std::os::unix::fs::symlink(&outside, target.join(".a.txt.archivist-part")).unwrap();
+
+        Restorer::new().target(&target).restore(&archive).unwrap();
+        assert_eq!(fs::read_to_string(&outside).unwrap(), "outside");
+        assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "archived");
+        assert!(fs::symlink_metadata(target.join(".a.txt.archivist-part")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        //zip entries can't carry the special bits, tarballs can
+        let folder = tempfile::tempdir().unwrap();
+        let archive = folder.path().join("full.tar.gz");
+        let mut tar = tar::Builder::new(GzEncoder::new(fs::File::create(&archive).unwrap(), flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(10);
+        header.set_mode(0o4755);
+        tar.append_data(&mut header, "run", &b"#!/bin/sh\n"[..]).unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+        let mode = |target: &Path| fs::metadata(target.join("run")).unwrap().permissions().mode() & 0o7777;
+
+        let target = folder.path().join("plain");
+        Restorer::new().target(&target).restore(&archive).unwrap();
+        assert_eq!(mode(&target), 0o755);
+
+        let target = folder.path().join("special");
+        Restorer::new().target(&target).special_bits(true).restore(&archive).unwrap();
+        assert_eq!(mode(&target), 0o4755);
+    }
+
+    #[test]
+    fn restores_listed_contents() {
+        let folder = tempfile::tempdir().unwrap();
+        let archive = folder.path().join("full.zip");
+        write_archive(&archive, &[("a.txt", b"original", 0o644)], b"original");
+        let target = folder.path().join("target");
+
+        let report = Restorer::new().target(&target).restore(&archive).unwrap();
+        assert!(matches!(report.entries[0].outcome, RestoreOutcome::Restored(_)));
+        assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "original");
+    }
+
+    #[test]
+    fn safe_paths() {
+        assert_eq!(safe_path("a.txt"), Some(PathBuf::from("a.txt")));
+        assert_eq!(safe_path("sales/2024/march.csv"), Some(Path::new("sales").join("2024").join("march.csv")));
+        assert_eq!(safe_path("./sales//march.csv"), Some(Path::new("sales").join("march.csv")));
+        assert_eq!(safe_path(r"sales\march.csv"), Some(Path::new("sales").join("march.csv")));
+        for name in ["", ".", "/etc/passwd", "../a.txt", "sales/../../a.txt", r"..\a.txt", r"\a.txt"] {
+            assert_eq!(safe_path(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn free_names() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("report.csv");
+        fs::write(&path, "").unwrap();
+        assert_eq!(free_name(&path), folder.path().join("report (1).csv"));
+        fs::write(folder.path().join("report (1).csv"), "").unwrap();
+        assert_eq!(free_name(&path), folder.path().join("report (2).csv"));
+        assert_eq!(free_name(&folder.path().join("notes")), folder.path().join("notes (1)"));
+    }
+}
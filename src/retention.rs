@@ -0,0 +1,417 @@
+//! Retention rules that prune old archives from an archive folder.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Local, TimeZone};
+
+use crate::age::TimeSpec;
+use crate::catalog::{self, Catalog, CATALOG_NAME};
+use crate::error::{Error, Result};
+use crate::format;
+use crate::manifest;
+use crate::naming;
+use crate::volume;
+
+/// Which archives to keep. Archives are grouped by their label (`full`, a
+/// file ending, ...) and every rule is applied to each group on its own;
+/// an archive is kept if any rule keeps it.
+///
+/// The daily, weekly and monthly rules keep the newest archive of each of
+/// the last n days, weeks and months that have archives
+/// (grandfather-father-son).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    last: Option<usize>,
+    daily: Option<usize>,
+    weekly: Option<usize>,
+    monthly: Option<usize>,
+    max_age: Option<TimeSpec>,
+}
+
+/// Maps a time to the day, week or month it is in.
+type Period = fn(&DateTime<Local>) -> (i32, u32, u32);
+
+/// Why an archive is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepReason {
+    Last,
+    Daily,
+    Weekly,
+    Monthly,
+    /// Newer than the maximum age.
+    Recent,
+}
+
+/// An archive made by the archivist, found in an archive folder.
+#[derive(Debug, Clone)]
+pub struct ArchiveInfo {
+    pub path: PathBuf,
+    pub label: String,
+    /// When the archive was written, from its name or its manifest.
+    pub created: DateTime<Local>,
+}
+
+/// The archives of a folder, split by a [`Retention`].
+#[derive(Debug, Default)]
+pub struct PrunePlan {
+    pub archive_folder: PathBuf,
+    /// Archives that are kept, with the rules keeping them.
+    pub keep: Vec<(ArchiveInfo, Vec<KeepReason>)>,
+    /// Archives that are removed.
+    pub remove: Vec<ArchiveInfo>,
+    /// Archives that weren't made by the archivist and are never touched.
+    pub foreign: Vec<PathBuf>,
+}
+
+impl Retention {
+    pub fn new() -> Self {
+        Retention::default()
+    }
+
+    /// Keeps the newest `count` archives.
+    pub fn keep_last(mut self, count: usize) -> Self {
+        self.last = Some(count);
+        self
+    }
+
+    /// Keeps the newest archive of each of the last `count` days.
+    pub fn keep_daily(mut self, count: usize) -> Self {
+        self.daily = Some(count);
+        self
+    }
+
+    /// Keeps the newest archive of each of the last `count` weeks.
+    pub fn keep_weekly(mut self, count: usize) -> Self {
+        self.weekly = Some(count);
+        self
+    }
+
+    /// Keeps the newest archive of each of the last `count` months.
+    pub fn keep_monthly(mut self, count: usize) -> Self {
+        self.monthly = Some(count);
+        self
+    }
+
+    /// Keeps all archives newer than `age`. On its own, this removes the
+    /// archives older than `age`.
+    pub fn max_age(mut self, age: TimeSpec) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Whether there are no rules, in which case nothing is pruned.
+    pub fn is_empty(&self) -> bool {
+        *self == Retention::default()
+    }
+
+    /// Decides which archives of `archive_folder` to keep.
+    pub fn plan(&self, archive_folder: &Path, now: DateTime<Local>) -> Result<PrunePlan> {
+        let catalog_path = archive_folder.join(CATALOG_NAME);
+        let catalog = if catalog_path.is_file() {
+            Some(Catalog::open(archive_folder)?)
+        } else {
+            None
+        };
+
+        let mut plan = PrunePlan {
+            archive_folder: archive_folder.to_path_buf(),
+            ..PrunePlan::default()
+        };
+        let mut groups: BTreeMap<String, Vec<ArchiveInfo>> = BTreeMap::new();
+        for path in catalog::archives_in(archive_folder)? {
+            match identify(&path, catalog.as_ref())? {
+                Some(info) => groups.entry(info.label.clone()).or_default().push(info),
+                None => plan.foreign.push(path),
+            }
+        }
+
+        for (_, mut archives) in groups {
+            //newest first
+            archives.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| b.path.cmp(&a.path)));
+            let mut reasons: Vec<Vec<KeepReason>> = vec![Vec::new(); archives.len()];
+
+            if let Some(count) = self.last {
+                for reasons in reasons.iter_mut().take(count) {
+                    reasons.push(KeepReason::Last);
+                }
+            }
+            let periods: [(Option<usize>, KeepReason, Period); 3] = [
+                (self.daily, KeepReason::Daily, |time| (time.year(), time.month(), time.day())),
+                (self.weekly, KeepReason::Weekly, |time| (time.iso_week().year(), time.iso_week().week(), 0)),
+                (self.monthly, KeepReason::Monthly, |time| (time.year(), time.month(), 0)),
+            ];
+            for (count, reason, period) in periods {
+                let Some(count) = count else {
+                    continue;
+                };
+                let mut seen = HashSet::new();
+                for (index, archive) in archives.iter().enumerate() {
+                    if seen.len() == count {
+                        break;
+                    }
+                    if seen.insert(period(&archive.created)) {
+                        reasons[index].push(reason);
+                    }
+                }
+            }
+            if let Some(age) = self.max_age {
+                let limit = DateTime::<Local>::from(age.resolve(now));
+                for (index, archive) in archives.iter().enumerate() {
+                    if archive.created > limit {
+                        reasons[index].push(KeepReason::Recent);
+                    }
+                }
+            }
+
+            for (archive, reasons) in archives.into_iter().zip(reasons) {
+                if reasons.is_empty() {
+                    plan.remove.push(archive);
+                } else {
+                    plan.keep.push((archive, reasons));
+                }
+            }
+        }
+        Ok(plan)
+    }
+}
+
+impl PrunePlan {
+    /// Deletes the archives to remove and takes them out of the catalog.
+    /// Returns every removed archive with the outcome.
+    pub fn execute(&self) -> Vec<(PathBuf, Result<()>)> {
+        let mut catalog = if self.archive_folder.join(CATALOG_NAME).is_file() {
+            Catalog::open(&self.archive_folder).ok()
+        } else {
+            None
+        };
+
+        self.remove
+            .iter()
+            .map(|archive| {
+                let result = volume::volumes(&archive.path)
+                    .iter()
+                    .try_for_each(|volume| fs::remove_file(volume).map_err(|err| Error::io(volume, err)))
+                    .and_then(|_| match &mut catalog {
+                        Some(catalog) => catalog.remove(&archive.path),
+                        None => Ok(()),
+                    });
+                (archive.path.clone(), result)
+            })
+            .collect()
+    }
+}
+
+/// The label and time of an archive made by the archivist, or `None` for
+/// other files. Only archives in the catalog or with a manifest count as
+/// made by the archivist, as any file can have a name like the default one;
+/// the default name only supplies the time.
+fn identify(path: &Path, catalog: Option<&Catalog>) -> Result<Option<ArchiveInfo>> {
+    let Some(name) = format::archive_stem(path) else {
+        return Ok(None);
+    };
+
+    //the catalog saves reading the whole archive for its manifest
+    let (label, created) = match catalog.map(|catalog| catalog.recorded(path)).transpose()?.flatten() {
+        Some((created, label)) => (label, Some(created)),
+        None => {
+            //archives that can't be read are left alone
+            let manifest = match manifest::read_manifest(path) {
+                Ok(Some(manifest)) => manifest,
+                Ok(None) | Err(_) => return Ok(None),
+            };
+            let created = DateTime::parse_from_rfc3339(&manifest.created).ok().map(|created| created.with_timezone(&Local));
+            (Some(manifest.label()), created)
+        }
+    };
+
+    let named = naming::parse_default_name(&name);
+    let named_created = named.as_ref().and_then(|(_, created)| Local.from_local_datetime(created).earliest());
+    let Some(label) = label.or_else(|| named.map(|(label, _)| label)) else {
+        return Ok(None);
+    };
+    let Some(created) = named_created.or(created) else {
+        return Ok(None);
+    };
+    Ok(Some(ArchiveInfo {
+        path: path.to_path_buf(),
+        label,
+        created,
+    }))
+}
+
+impl fmt::Display for KeepReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeepReason::Last => write!(f, "last"),
+            KeepReason::Daily => write!(f, "daily"),
+            KeepReason::Weekly => write!(f, "weekly"),
+            KeepReason::Monthly => write!(f, "monthly"),
+            KeepReason::Recent => write!(f, "recent"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+    use crate::manifest::{Manifest, MANIFEST_NAME};
+
+    /// Writes a zip named with the default template, with a manifest if
+    /// `label` is given.
+    fn write_archive(folder: &Path, name_label: &str, label: Option<&str>, (year, month, day, hour): (i32, u32, u32, u32)) -> PathBuf {
+        let path = folder.join(format!("{}_archive_{}_{}_{}_{}_0.zip", name_label, year, month, day, hour));
+        let mut zip = ZipWriter::new(fs::File::create(&path).unwrap());
+        zip.start_file("a.txt", SimpleFileOptions::default()).unwrap();
+        if let Some(label) = label {
+            let manifest = Manifest {
+                version: crate::VERSION.to_string(),
+                hostname: "host".to_string(),
+                created: time(year, month, day, hour).to_rfc3339(),
+                folder: PathBuf::from("/data"),
+                mode: "all".to_string(),
+                label: Some(label.to_string()),
+                extension: None,
+                identifier: None,
+                ignore: Vec::new(),
+                files: Vec::new(),
+            };
+            zip.start_file(MANIFEST_NAME, SimpleFileOptions::default()).unwrap();
+            zip.write_all(&manifest.to_json()).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    /// An archive folder with archives made at the given times.
+    fn folder(label: &str, times: &[(i32, u32, u32, u32)]) -> tempfile::TempDir {
+        let folder = tempfile::tempdir().unwrap();
+        for &time in times {
+            write_archive(folder.path(), label, Some(label), time);
+        }
+        folder
+    }
+
+    fn time(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap()
+    }
+
+    /// The times of the kept archives, newest first.
+    fn kept(plan: &PrunePlan) -> Vec<DateTime<Local>> {
+        plan.keep.iter().map(|(archive, _)| archive.created).collect()
+    }
+
+    #[test]
+    fn keep_last() {
+        let folder = folder("full", &[(2024, 3, 1, 10), (2024, 3, 2, 10), (2024, 3, 3, 10)]);
+        let plan = Retention::new().keep_last(2).plan(folder.path(), time(2024, 3, 4, 0)).unwrap();
+        assert_eq!(kept(&plan), [time(2024, 3, 3, 10), time(2024, 3, 2, 10)]);
+        assert_eq!(plan.remove.len(), 1);
+        assert_eq!(plan.remove[0].created, time(2024, 3, 1, 10));
+        assert!(plan.keep.iter().all(|(_, reasons)| reasons == &[KeepReason::Last]));
+    }
+
+    #[test]
+    fn keep_daily_and_monthly() {
+        let times = [(2024, 1, 20, 10), (2024, 2, 10, 10), (2024, 3, 1, 9), (2024, 3, 1, 18), (2024, 3, 2, 10)];
+        let folder = folder("full", &times);
+        let retention = Retention::new().keep_daily(2).keep_monthly(2);
+        let plan = retention.plan(folder.path(), time(2024, 3, 3, 0)).unwrap();
+
+        let reasons: Vec<(DateTime<Local>, Vec<KeepReason>)> =
+            plan.keep.iter().map(|(archive, reasons)| (archive.created, reasons.clone())).collect();
+        assert_eq!(
+            reasons,
+            [
+                (time(2024, 3, 2, 10), vec![KeepReason::Daily, KeepReason::Monthly]),
+                (time(2024, 3, 1, 18), vec![KeepReason::Daily]),
+                (time(2024, 2, 10, 10), vec![KeepReason::Monthly]),
+            ]
+        );
+        let removed: Vec<DateTime<Local>> = plan.remove.iter().map(|archive| archive.created).collect();
+        assert_eq!(removed, [time(2024, 3, 1, 9), time(2024, 1, 20, 10)]);
+    }
+
+    #[test]
+    fn keep_weekly() {
+        //2024-03-04 is a monday
+        let folder = folder("full", &[(2024, 3, 3, 10), (2024, 3, 4, 10), (2024, 3, 5, 10), (2024, 2, 20, 10)]);
+        let plan = Retention::new().keep_weekly(2).plan(folder.path(), time(2024, 3, 6, 0)).unwrap();
+        assert_eq!(kept(&plan), [time(2024, 3, 5, 10), time(2024, 3, 3, 10)]);
+    }
+
+    #[test]
+    fn max_age() {
+        let folder = folder("full", &[(2024, 3, 1, 10), (2024, 3, 9, 10)]);
+        let retention = Retention::new().max_age("5d".parse().unwrap());
+        let plan = retention.plan(folder.path(), time(2024, 3, 10, 0)).unwrap();
+        assert_eq!(kept(&plan), [time(2024, 3, 9, 10)]);
+        assert_eq!(plan.keep[0].1, [KeepReason::Recent]);
+        assert_eq!(plan.remove.len(), 1);
+    }
+
+    #[test]
+    fn groups_and_foreign_files() {
+        let folder = folder("csv", &[(2024, 3, 1, 10), (2024, 3, 2, 10)]);
+        write_archive(folder.path(), "pdf", Some("pdf"), (2024, 3, 1, 10));
+        fs::write(folder.path().join("holiday.zip"), "").unwrap();
+        fs::write(folder.path().join("notes.txt"), "").unwrap();
+
+        let plan = Retention::new().keep_last(1).plan(folder.path(), time(2024, 3, 3, 0)).unwrap();
+        let mut labels: Vec<&str> = plan.keep.iter().map(|(archive, _)| archive.label.as_str()).collect();
+        labels.sort();
+        assert_eq!(labels, ["csv", "pdf"]);
+        assert_eq!(plan.remove.len(), 1);
+        assert_eq!(plan.foreign, [folder.path().join("holiday.zip")]);
+    }
+
+    #[test]
+    fn default_names_alone_are_foreign() {
+        let folder = folder("report", &[(2024, 3, 2, 10)]);
+        let dropped = write_archive(folder.path(), "report", None, (2024, 1, 1, 1));
+        fs::write(folder.path().join("report_archive_2024_1_2_1_1.zip"), "").unwrap();
+
+        let plan = Retention::new().keep_last(1).plan(folder.path(), time(2024, 3, 3, 0)).unwrap();
+        assert_eq!(kept(&plan), [time(2024, 3, 2, 10)]);
+        assert!(plan.remove.is_empty());
+        assert_eq!(plan.foreign, [dropped, folder.path().join("report_archive_2024_1_2_1_1.zip")]);
+    }
+
+    #[test]
+    fn imported_archives() {
+        let folder = tempfile::tempdir().unwrap();
+        let old = write_archive(folder.path(), "full", None, (2024, 3, 1, 10));
+        write_archive(folder.path(), "full", Some("full"), (2024, 3, 2, 10));
+        Catalog::open(folder.path()).unwrap().import(&old).unwrap();
+
+        let plan = Retention::new().keep_last(1).plan(folder.path(), time(2024, 3, 3, 0)).unwrap();
+        assert_eq!(kept(&plan), [time(2024, 3, 2, 10)]);
+        assert_eq!(plan.remove.len(), 1);
+        assert_eq!(plan.remove[0].path, old);
+        assert_eq!(plan.remove[0].label, "full");
+    }
+
+    #[test]
+    fn execute() {
+        let folder = folder("full", &[(2024, 3, 1, 10), (2024, 3, 2, 10)]);
+        let plan = Retention::new().keep_last(1).plan(folder.path(), time(2024, 3, 3, 0)).unwrap();
+        let removed = plan.execute();
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].1.is_ok());
+        assert!(!removed[0].0.exists());
+        assert!(plan.keep[0].0.path.exists());
+    }
+
+    #[test]
+    fn empty() {
+        assert!(Retention::new().is_empty());
+        assert!(!Retention::new().keep_last(0).is_empty());
+    }
+}
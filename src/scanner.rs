@@ -0,0 +1,468 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+use crate::age::AgeFilter;
+use crate::binary::{self, BinaryAction, BinaryClass, BinaryPolicy};
+use crate::error::{Error, Result};
+
+/// Name of the folder the archives are written to, inside the scanned folder.
+pub const ARCHIVE_FOLDER: &str = "archive";
+
+/// Name of the ignore file read from the scanned folder and its subfolders.
+pub const IGNORE_FILE: &str = ".archivistignore";
+
+/// A file that can be archived.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    /// Full path of the file.
+    pub path: PathBuf,
+    /// Name the file gets inside the archive: the path relative to the
+    /// scanned folder, with `/` as separator.
+    pub name: String,
+    /// File ending without the dot, if the file has one.
+    pub extension: Option<String>,
+    /// Size in bytes.
+    pub size: u64,
+}
+
+/// Why a file or folder was left out of a scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The name starts with a dot.
+    Hidden,
+    /// The name is on the ignore list, e.g. the tool's own executable.
+    Ignored,
+    /// The file or folder matches a pattern of an ignore file.
+    Excluded,
+    /// The file is a binary that is excluded by the binary policy.
+    Binary(BinaryClass),
+    /// The file is newer than the age filter allows.
+    TooNew,
+    /// The file is older than the age filter allows.
+    TooOld,
+    /// The file is smaller than the minimum size.
+    TooSmall,
+    /// The file is larger than the maximum size.
+    TooLarge,
+    /// The size or time of the file could not be read.
+    Unreadable,
+}
+
+/// A file or folder that was left out of a scan. Folders end with `/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub name: String,
+    pub reason: SkipReason,
+}
+
+/// Collects the files of a folder.
+///
+/// Hidden files and folders (names starting with a dot) and the tool's own
+/// executable are left out, as well as files outside the limits of the
+/// [`age`](Scanner::age) and size filters. Binaries are left out unless the
+/// [`binaries`](Scanner::binaries) policy says otherwise. Subfolders are only
+/// scanned in recursive mode, and neither the `archive/` subfolder nor the
+/// [`archive_folder`](Scanner::archive_folder) the archives go to ever is.
+///
+/// Files and folders matching the patterns of a `.archivistignore` file
+/// (and optionally `.gitignore` files) are left out as well. The patterns
+/// follow the gitignore syntax and apply to the folder of the ignore file
+/// and its subfolders.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    folder: PathBuf,
+    ignore_files: Vec<String>,
+    recursive: bool,
+    age: Option<AgeFilter>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    gitignore: bool,
+    binaries: BinaryPolicy,
+    /// Folder the archives are written to, if it isn't `archive/`.
+    archive_folder: Option<PathBuf>,
+    /// The running executable, which is never archived.
+    own_executable: Option<PathBuf>,
+}
+
+/// The files found by a [`Scanner`].
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    /// The scanned folder.
+    pub folder: PathBuf,
+    /// Files that can be archived, sorted by name.
+    pub files: Vec<FileEntry>,
+    /// The different file endings of `files`, in order of appearance.
+    pub file_endings: Vec<String>,
+    /// Subfolders found in recursive mode, relative to `folder` with `/` as
+    /// separator, parents before their children.
+    pub directories: Vec<String>,
+    /// Files and folders that were left out, with the reason.
+    pub skipped: Vec<Skipped>,
+    /// Binaries that go into an archive of their own, by class.
+    pub own_archive: Vec<(BinaryClass, FileEntry)>,
+    /// File names that are never archived.
+    pub ignore_files: Vec<String>,
+    /// Number of files in the folder, including the files of subfolders in
+    /// recursive mode.
+    pub file_count: usize,
+}
+
+impl Scanner {
+    pub fn new(folder: impl Into<PathBuf>) -> Self {
+        Scanner {
+            folder: folder.into(),
+            ignore_files: vec!["the_archivist.exe".to_string()],
+            recursive: false,
+            age: None,
+            min_size: None,
+            max_size: None,
+            gitignore: false,
+            binaries: BinaryPolicy::default(),
+            archive_folder: None,
+            own_executable: env::current_exe().and_then(|path| path.canonicalize()).ok(),
+        }
+    }
+
+    /// Also collects the files of all subfolders.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Only collects files whose age is within the limits of `filter`.
+    pub fn age(mut self, filter: AgeFilter) -> Self {
+        self.age = Some(filter);
+        self
+    }
+
+    /// Only collects files of at least `size` bytes.
+    pub fn min_size(mut self, size: u64) -> Self {
+        self.min_size = Some(size);
+        self
+    }
+
+    /// Only collects files of at most `size` bytes.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Also leaves out the files listed in `.gitignore` files.
+    pub fn gitignore(mut self, gitignore: bool) -> Self {
+        self.gitignore = gitignore;
+        self
+    }
+
+    /// Sets what happens to executables of each class (ELF, PE and Mach-O
+    /// executables are excluded by default).
+    pub fn binaries(mut self, policy: BinaryPolicy) -> Self {
+        self.binaries = policy;
+        self
+    }
+
+    /// Sets the folder the archives are written to when it isn't the
+    /// `archive/` subfolder, so it isn't archived if it is inside the folder.
+    pub fn archive_folder(mut self, folder: impl Into<PathBuf>) -> Self {
+        self.archive_folder = Some(folder.into());
+        self
+    }
+
+    /// Adds a file name that should never be archived.
+    pub fn ignore(mut self, file_name: impl Into<String>) -> Self {
+        self.ignore_files.push(file_name.into());
+        self
+    }
+
+    pub fn scan(&self) -> Result<ScanResult> {
+        let mut result = ScanResult {
+            folder: self.folder.clone(),
+            files: Vec::new(),
+            file_endings: Vec::new(),
+            directories: Vec::new(),
+            skipped: Vec::new(),
+            own_archive: Vec::new(),
+            ignore_files: self.ignore_files.clone(),
+            file_count: 0,
+        };
+
+        let now = Local::now();
+        //compared after resolving links, the destination may be given by
+        //another path than the folder
+        let archive_folder = self.archive_folder.as_ref().and_then(|folder| folder.canonicalize().ok());
+        self.scan_folder(&self.folder, "", now, &[], archive_folder.as_deref(), &mut result)?;
+        Ok(result)
+    }
+
+    /// Scans one folder; `prefix` is its path relative to the scanned folder
+    /// including a trailing `/`, or empty for the scanned folder itself.
+    /// `ignores` are the ignore files of the parent folders, outermost first.
+    /// `archive_folder` is the resolved folder the archives are written to.
+    fn scan_folder(
+        &self,
+        folder: &Path,
+        prefix: &str,
+        now: DateTime<Local>,
+        ignores: &[&Gitignore],
+        archive_folder: Option<&Path>,
+        result: &mut ScanResult,
+    ) -> Result<()> {
+        let local_ignores = self.read_ignore_files(folder)?;
+        let mut ignores = ignores.to_vec();
+        if let Some(local_ignores) = &local_ignores {
+            ignores.push(local_ignores);
+        }
+
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(folder).map_err(|err| Error::io(folder, err))? {
+            let entry = entry.map_err(|err| Error::io(folder, err))?;
+            paths.push(entry.path());
+        }
+        paths.sort();
+
+        for path in paths {
+            let file_name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(file_name) => file_name.to_string(),
+                None => continue,
+            };
+            let name = format!("{}{}", prefix, file_name);
+
+            // don't follow symlinked folders, they could point back up the tree
+            let is_dir = fs::symlink_metadata(&path).map(|meta| meta.is_dir()).unwrap_or(false);
+            if is_dir {
+                //never archive the archives
+                if !self.recursive || (prefix.is_empty() && file_name == ARCHIVE_FOLDER) {
+                    continue;
+                }
+                if archive_folder.is_some_and(|archive_folder| path.canonicalize().is_ok_and(|path| path == archive_folder)) {
+                    continue;
+                }
+                if is_excluded(&ignores, &path, true) {
+                    result.skip(format!("{}/", name), SkipReason::Excluded);
+                    continue;
+                }
+                if file_name.starts_with('.') {
+                    result.skip(format!("{}/", name), SkipReason::Hidden);
+                    continue;
+                }
+                result.directories.push(name.clone());
+                self.scan_folder(&path, &format!("{}/", name), now, &ignores, archive_folder, result)?;
+                continue;
+            }
+
+            //ignore everything that is not a regular file
+            if !path.is_file() {
+                continue;
+            }
+            result.file_count += 1;
+
+            //ignore the executable and everything that starts with a dot
+            if self.ignore_files.contains(&file_name) || self.is_own_executable(&path, &file_name) {
+                result.skip(name, SkipReason::Ignored);
+                continue;
+            }
+            if is_excluded(&ignores, &path, false) {
+                result.skip(name, SkipReason::Excluded);
+                continue;
+            }
+            if file_name.starts_with('.') {
+                result.skip(name, SkipReason::Hidden);
+                continue;
+            }
+
+            let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_string);
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    result.skip(name, SkipReason::Unreadable);
+                    continue;
+                }
+            };
+            if let Some(reason) = self.age.as_ref().and_then(|age| age.check(&metadata, now)) {
+                result.skip(name, reason);
+                continue;
+            }
+
+            let size = metadata.len();
+            if self.min_size.is_some_and(|min_size| size < min_size) {
+                result.skip(name, SkipReason::TooSmall);
+                continue;
+            }
+            if self.max_size.is_some_and(|max_size| size > max_size) {
+                result.skip(name, SkipReason::TooLarge);
+                continue;
+            }
+
+            // files that can't be read are left to the archiver, which
+            // reports them
+            let class = binary::detect(&path, &metadata).ok().flatten();
+            let entry = FileEntry {
+                path,
+                name,
+                extension,
+                size,
+            };
+            match class.map(|class| (class, self.binaries.action(class))) {
+                Some((class, BinaryAction::Exclude)) => {
+                    result.skip(entry.name, SkipReason::Binary(class));
+                    continue;
+                }
+                Some((class, BinaryAction::OwnArchive)) => {
+                    result.own_archive.push((class, entry));
+                    continue;
+                }
+                Some((_, BinaryAction::Include)) | None => {}
+            }
+
+            if let Some(extension) = &entry.extension {
+                if !result.file_endings.contains(extension) {
+                    result.file_endings.push(extension.clone());
+                }
+            }
+            result.files.push(entry);
+        }
+
+        Ok(())
+    }
+
+    /// Whether `path` is the running executable, whatever its name.
+    fn is_own_executable(&self, path: &Path, file_name: &str) -> bool {
+        match &self.own_executable {
+            Some(own) if own.file_name().is_some_and(|own_name| own_name == file_name) => {
+                path.canonicalize().is_ok_and(|path| &path == own)
+            }
+            _ => false,
+        }
+    }
+
+    /// The patterns of the ignore files in `folder`, if it has any. Patterns
+    /// of `.archivistignore` take precedence over those of `.gitignore`.
+    fn read_ignore_files(&self, folder: &Path) -> Result<Option<Gitignore>> {
+        let mut builder = GitignoreBuilder::new(folder);
+        let mut found = false;
+        let gitignore = if self.gitignore { Some(".gitignore") } else { None };
+        for file_name in gitignore.into_iter().chain([IGNORE_FILE]) {
+            let path = folder.join(file_name);
+            if !path.is_file() {
+                continue;
+            }
+            if let Some(err) = builder.add(&path) {
+                return Err(Error::InvalidPattern(format!("{}: {}", path.display(), err)));
+            }
+            found = true;
+        }
+        if !found {
+            return Ok(None);
+        }
+        builder
+            .build()
+            .map(Some)
+            .map_err(|err| Error::InvalidPattern(format!("{}: {}", folder.display(), err)))
+    }
+}
+
+/// Whether the innermost ignore file with a matching pattern ignores `path`.
+fn is_excluded(ignores: &[&Gitignore], path: &Path, is_dir: bool) -> bool {
+    for ignore in ignores.iter().rev() {
+        match ignore.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    false
+}
+
+impl FileEntry {
+    /// Name of the file without the folders leading to it.
+    pub fn file_name(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or(&self.name)
+    }
+
+    /// The folders leading to the file inside the archive, outermost first,
+    /// e.g. `["a", "a/b"]` for `a/b/c.txt`.
+    pub fn parent_directories(&self) -> impl Iterator<Item = &str> {
+        self.name.match_indices('/').map(move |(index, _)| &self.name[..index])
+    }
+}
+
+impl ScanResult {
+    /// Folder the archives of this scan are written to.
+    pub fn archive_folder(&self) -> PathBuf {
+        self.folder.join(ARCHIVE_FOLDER)
+    }
+
+    /// Names of the hidden and ignored files and folders, including the ones
+    /// matched by ignore files.
+    pub fn ignored(&self) -> Vec<&str> {
+        self.skipped
+            .iter()
+            .filter(|skipped| matches!(skipped.reason, SkipReason::Hidden | SkipReason::Ignored | SkipReason::Excluded))
+            .map(|skipped| skipped.name.as_str())
+            .collect()
+    }
+
+    /// Names and classes of the binaries that were left out.
+    pub fn binaries(&self) -> Vec<(&str, BinaryClass)> {
+        self.skipped
+            .iter()
+            .filter_map(|skipped| match skipped.reason {
+                SkipReason::Binary(class) => Some((skipped.name.as_str(), class)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Names of the files that were left out by the age and size filters.
+    pub fn filtered(&self) -> Vec<(&str, SkipReason)> {
+        self.skipped
+            .iter()
+            .filter(|skipped| {
+                matches!(
+                    skipped.reason,
+                    SkipReason::TooNew | SkipReason::TooOld | SkipReason::TooSmall | SkipReason::TooLarge
+                )
+            })
+            .map(|skipped| (skipped.name.as_str(), skipped.reason))
+            .collect()
+    }
+
+    /// Names of the files whose size or time could not be read.
+    pub fn unreadable(&self) -> Vec<&str> {
+        self.skipped
+            .iter()
+            .filter(|skipped| skipped.reason == SkipReason::Unreadable)
+            .map(|skipped| skipped.name.as_str())
+            .collect()
+    }
+
+    fn skip(&mut self, name: String, reason: SkipReason) {
+        self.skipped.push(Skipped { name, reason });
+    }
+
+    /// Files with the given file ending.
+    pub fn files_with_extension<'a>(&'a self, extension: &'a str) -> impl Iterator<Item = &'a FileEntry> {
+        self.files.iter().filter(move |file| file.extension.as_deref() == Some(extension))
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Hidden => write!(f, "hidden file"),
+            SkipReason::Ignored => write!(f, "always ignored"),
+            SkipReason::Excluded => write!(f, "in ignore file"),
+            SkipReason::Binary(class) => write!(f, "binary file, {}", class),
+            SkipReason::TooNew => write!(f, "too recent"),
+            SkipReason::TooOld => write!(f, "too old"),
+            SkipReason::TooSmall => write!(f, "too small"),
+            SkipReason::TooLarge => write!(f, "too large"),
+            SkipReason::Unreadable => write!(f, "could not be read"),
+        }
+    }
+}
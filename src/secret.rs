@@ -0,0 +1,169 @@
+//! Where the password of encrypted archives comes from: a file descriptor,
+//! the `ARCHIVIST_PASSWORD` environment variable or a hidden prompt. There
+//! is no option for the password itself, since the arguments of a process
+//! can be seen by every user of the machine.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use the_archivist::Password;
+use zeroize::Zeroizing;
+
+/// Environment variable holding the password.
+pub const PASSWORD_ENV: &str = "ARCHIVIST_PASSWORD";
+
+/// Reads the password from `fd`, from the environment or else from a hidden
+/// prompt on the terminal. A new password (`confirm`) is asked for twice.
+pub fn password(fd: Option<i32>, confirm: bool) -> Result<Password, String> {
+    let mut password = if let Some(fd) = fd {
+        read_fd(fd).map_err(|err| format!("could not read the password from file descriptor {}: {}", fd, err))?
+    } else if let Some(password) = env::var_os(PASSWORD_ENV) {
+        Zeroizing::new(
+            password
+                .into_string()
+                .map_err(|_| format!("{} is not valid UTF-8", PASSWORD_ENV))?,
+        )
+    } else {
+        prompt(confirm)?
+    };
+
+    if password.is_empty() {
+        return Err("the password is empty".to_string());
+    }
+    //moves the contents without copying them
+    Ok(Password::new(std::mem::take(&mut *password)))
+}
+
+fn prompt(confirm: bool) -> Result<Zeroizing<String>, String> {
+    if !io::stdin().is_terminal() {
+        return Err(format!("no password given, set {} or use --password-fd", PASSWORD_ENV));
+    }
+    let password = read_hidden("Password: ").map_err(|err| format!("could not read the password: {}", err))?;
+    if confirm {
+        let repeated = read_hidden("Repeat the password: ").map_err(|err| format!("could not read the password: {}", err))?;
+        if *repeated != *password {
+            return Err("the passwords don't match".to_string());
+        }
+    }
+    Ok(password)
+}
+
+/// The first line of what is written to the file descriptor `fd`, e.g.
+/// `--password-fd 3 3< secret.txt`.
+#[cfg(unix)]
+fn read_fd(fd: i32) -> io::Result<Zeroizing<String>> {
+    use std::fs::File;
+    use std::os::unix::io::FromRawFd;
+
+    //the standard streams are still used by the process, and closing them
+    //after reading would break its output
+    if (0..=2).contains(&fd) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a standard stream, use a descriptor from 3 on", fd),
+        ));
+    }
+    // SAFETY: F_GETFD only reads the flags of the descriptor and fails with
+    // EBADF if it isn't open
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor is open and isn't one of the standard streams,
+    // it was passed to the process only for the password, so nothing else
+    // owns it. It is closed after reading.
+    let file = unsafe { File::from_raw_fd(fd) };
+    read_line(&file)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> io::Result<Zeroizing<String>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "file descriptors are only supported on unix"))
+}
+
+/// Reads a line from the terminal without showing it.
+#[cfg(unix)]
+fn read_hidden(prompt: &str) -> io::Result<Zeroizing<String>> {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+    let mut original = std::mem::MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: the descriptor is the open terminal and tcgetattr only writes
+    // the termios it points to
+    if unsafe { libc::tcgetattr(fd, original.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: tcgetattr succeeded, so it filled in the whole termios
+    let original = unsafe { original.assume_init() };
+    let mut hidden = original;
+    hidden.c_lflag &= !libc::ECHO;
+    //the newline typed at the end is still shown
+    hidden.c_lflag |= libc::ECHONL;
+    // SAFETY: the descriptor is the open terminal and `hidden` is a valid
+    // termios, a copy of the current settings
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &hidden) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    //turns echo back on however reading ends; declared after `tty`, so it
+    //runs while the terminal is still open
+    let _echo = EchoGuard { fd, original };
+
+    tty.write_all(prompt.as_bytes())?;
+    tty.flush()?;
+    read_line(&tty)
+}
+
+/// Puts back the terminal settings saved before echo was turned off.
+#[cfg(unix)]
+struct EchoGuard {
+    fd: i32,
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl Drop for EchoGuard {
+    fn drop(&mut self) {
+        // SAFETY: the descriptor is the terminal opened by read_hidden, which
+        // is closed only after the guard, and `original` came from tcgetattr
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Reads up to the end of the line, byte by byte, so no buffer is left
+/// holding the password; `line` is wiped when it is dropped.
+#[cfg(unix)]
+fn read_line(mut input: &std::fs::File) -> io::Result<Zeroizing<String>> {
+    use std::io::Read;
+
+    let mut line = Zeroizing::new(Vec::with_capacity(256));
+    let mut byte = [0u8];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => line.push(byte[0]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    match String::from_utf8(std::mem::take(&mut *line)) {
+        Ok(line) => Ok(Zeroizing::new(line)),
+        Err(err) => {
+            drop(Zeroizing::new(err.into_bytes()));
+            Err(io::Error::new(io::ErrorKind::InvalidData, "the password is not valid UTF-8"))
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn read_hidden(_prompt: &str) -> io::Result<Zeroizing<String>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("hidden prompts are only supported on unix, set {}", PASSWORD_ENV),
+    ))
+}
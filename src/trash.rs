@@ -0,0 +1,224 @@
+//! Moving files to the trash as described by the freedesktop.org trash
+//! specification, so desktop file managers can restore them.
+#![cfg_attr(not(unix), allow(dead_code))]
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+/// Moves `path` to the trash and returns where it ended up.
+///
+/// Files are moved to the home trash (`$XDG_DATA_HOME/Trash`, by default
+/// `~/.local/share/Trash`). Files on another device than the home trash go
+/// to a trash at the top of their mount point instead, as files can't be
+/// renamed across devices: `$topdir/.Trash/$uid` if the administrator set up
+/// `$topdir/.Trash`, otherwise `$topdir/.Trash-$uid`.
+#[cfg(unix)]
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    // resolve the folder only, a symlink has to be trashed itself and not
+    // the file it points to
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let parent = match path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        Some(parent) => parent.canonicalize()?,
+        None => env::current_dir()?,
+    };
+    let path = parent.join(file_name);
+    let device = fs::symlink_metadata(&path)?.dev();
+
+    let home_trash = home_trash()?;
+    fs::create_dir_all(&home_trash)?;
+    if fs::metadata(&home_trash)?.dev() == device {
+        return trash_into(&home_trash, &path, &path);
+    }
+
+    let top = mount_point(&path, device)?;
+    let trash = top_trash(&top)?;
+    // trashes at the top of a mount point store paths relative to it
+    let relative = path.strip_prefix(&top).unwrap_or(&path).to_path_buf();
+    trash_into(&trash, &path, &relative)
+}
+
+#[cfg(not(unix))]
+pub fn move_to_trash(_path: &Path) -> io::Result<PathBuf> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "the trash is only supported on unix systems"))
+}
+
+/// The trash of the user at the top of a mount point, `$topdir/.Trash/$uid`
+/// or else `$topdir/.Trash-$uid`.
+#[cfg(unix)]
+fn top_trash(top: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    // SAFETY: getuid has no preconditions and can't fail
+    let uid = unsafe { libc::getuid() };
+    // the shared trash is only trusted if it is a real folder with the
+    // sticky bit, so users can't remove each other's trash
+    let shared = top.join(".Trash");
+    if let Ok(metadata) = fs::symlink_metadata(&shared) {
+        if metadata.is_dir() && metadata.mode() & 0o1000 != 0 {
+            if let Ok(trash) = user_folder(&shared.join(uid.to_string()), uid) {
+                return Ok(trash);
+            }
+        }
+    }
+    user_folder(&top.join(format!(".Trash-{}", uid)), uid)
+}
+
+/// Creates `folder` only readable by the user if it doesn't exist, and
+/// checks that it is a folder of the user and not a symlink.
+#[cfg(unix)]
+fn user_folder(folder: &Path, uid: u32) -> io::Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    match fs::DirBuilder::new().mode(0o700).create(folder) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(err),
+    }
+    let metadata = fs::symlink_metadata(folder)?;
+    if !metadata.is_dir() || metadata.uid() != uid {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a folder owned by the user", folder.display()),
+        ));
+    }
+    Ok(folder.to_path_buf())
+}
+
+/// `$XDG_DATA_HOME/Trash`, falling back to `~/.local/share/Trash`.
+fn home_trash() -> io::Result<PathBuf> {
+    if let Some(data_home) = env::var_os("XDG_DATA_HOME").filter(|dir| Path::new(dir).is_absolute()) {
+        return Ok(PathBuf::from(data_home).join("Trash"));
+    }
+    let home = env::var_os("HOME").ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    Ok(PathBuf::from(home).join(".local/share/Trash"))
+}
+
+/// The topmost folder above `path` that is still on `device`.
+#[cfg(unix)]
+fn mount_point(path: &Path, device: u64) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut top = path.parent().unwrap_or(path).to_path_buf();
+    while let Some(parent) = top.parent() {
+        if fs::metadata(parent)?.dev() != device {
+            break;
+        }
+        top = parent.to_path_buf();
+    }
+    Ok(top)
+}
+
+/// Writes the `.trashinfo` file and moves `path` into `trash/files`.
+/// `original` is the path recorded in the info file.
+#[cfg(unix)]
+fn trash_into(trash: &Path, path: &Path, original: &Path) -> io::Result<PathBuf> {
+    let files = trash.join("files");
+    let info = trash.join("info");
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_os_string();
+
+    // reserve a free name by creating its info file, appending a counter if
+    // a file with the same name is already in the trash
+    let mut counter = 1;
+    let (name, mut info_file) = loop {
+        let mut name = file_name.clone();
+        if counter > 1 {
+            name.push(format!(".{}", counter));
+        }
+        let info_path = info.join(info_name(&name));
+        match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(info_file) if !files.join(&name).exists() => break (name, info_file),
+            Ok(_) => {
+                fs::remove_file(&info_path)?;
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+        counter += 1;
+    };
+
+    let info_path = info.join(info_name(&name));
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(original),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    if let Err(err) = info_file.write_all(contents.as_bytes()) {
+        let _ = fs::remove_file(&info_path);
+        return Err(err);
+    }
+
+    let target = files.join(&name);
+    if let Err(err) = fs::rename(path, &target) {
+        let _ = fs::remove_file(&info_path);
+        return Err(err);
+    }
+    Ok(target)
+}
+
+/// The name of the `.trashinfo` file of a file named `name` in the trash.
+fn info_name(name: &OsStr) -> OsString {
+    let mut info_name = name.to_os_string();
+    info_name.push(".trashinfo");
+    info_name
+}
+
+/// Percent-encodes a path for the `Path=` key of a `.trashinfo` file. The
+/// raw bytes are encoded, so names that aren't UTF-8 are kept as they are.
+#[cfg(unix)]
+fn encode_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(encode_path(Path::new("/home/ann/report-1_v2.txt")), "/home/ann/report-1_v2.txt");
+        assert_eq!(encode_path(Path::new("/tmp/a b%.txt")), "/tmp/a%20b%25.txt");
+        assert_eq!(encode_path(Path::new("/tmp/ä")), "/tmp/%C3%A4");
+        assert_eq!(encode_path(Path::new(OsStr::from_bytes(b"/tmp/\xff"))), "/tmp/%FF");
+    }
+
+    #[test]
+    fn trash_names() {
+        let folder = tempfile::tempdir().unwrap();
+        let trash = folder.path().join("Trash");
+        for _ in 0..2 {
+            fs::write(folder.path().join("a.txt"), "").unwrap();
+            trash_into(&trash, &folder.path().join("a.txt"), Path::new("/data/a.txt")).unwrap();
+        }
+
+        assert!(trash.join("files/a.txt").is_file());
+        assert!(trash.join("files/a.txt.2").is_file());
+        let info = fs::read_to_string(trash.join("info/a.txt.2.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=/data/a.txt\nDeletionDate="));
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crc32fast::Hasher;
+use zip::ZipArchive;
+
+use crate::format;
+use crate::password::Password;
+use crate::report::ArchiveResult;
+use crate::scanner::FileEntry;
+use crate::volume;
+
+/// Outcome of checking a written archive against the archived files.
+#[derive(Debug, Clone, Default)]
+pub struct Verification {
+    /// Files whose entry matches the file on disk.
+    pub verified: Vec<PathBuf>,
+    /// Files whose entry is missing, unreadable or different, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl Verification {
+    pub fn is_verified(&self, path: &Path) -> bool {
+        self.verified.iter().any(|verified| verified == path)
+    }
+}
+
+/// Reopens the archive of `result` and compares the size and CRC-32 of every
+/// entry with the file it was made from.
+///
+/// Every entry is read in full, so damaged compressed data is detected as
+/// well; zip entries are additionally checked against their stored CRC.
+pub fn verify_archive(result: &ArchiveResult) -> Verification {
+    let entries = if result.format.is_tar() {
+        read_tar_entries(result)
+    } else {
+        read_zip_entries(result)
+    };
+
+    let mut verification = Verification::default();
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(err) => {
+            for file in &result.files {
+                verification.failed.push((file.path.clone(), format!("archive can't be read: {}", err)));
+            }
+            return verification;
+        }
+    };
+
+    for file in &result.files {
+        match compare(file, entries.get(&file.name)) {
+            Ok(()) => verification.verified.push(file.path.clone()),
+            Err(reason) => verification.failed.push((file.path.clone(), reason)),
+        }
+    }
+    verification
+}
+
+/// Size and CRC-32 of an archive entry, or why it couldn't be read.
+type EntryCheck = std::result::Result<(u64, u32), String>;
+
+fn compare(file: &FileEntry, entry: Option<&EntryCheck>) -> std::result::Result<(), String> {
+    let (entry_size, entry_crc) = match entry {
+        Some(Ok(check)) => *check,
+        Some(Err(reason)) => return Err(reason.clone()),
+        None => return Err("missing in archive".to_string()),
+    };
+
+    let (size, crc) = File::open(&file.path)
+        .and_then(checksum)
+        .map_err(|err| format!("file can't be read: {}", err))?;
+
+    if entry_size != size {
+        return Err(format!("size differs: {} bytes in archive, {} bytes on disk", entry_size, size));
+    }
+    if entry_crc != crc {
+        return Err(format!("checksum differs: {:08x} in archive, {:08x} on disk", entry_crc, crc));
+    }
+    Ok(())
+}
+
+fn read_zip_entries(result: &ArchiveResult) -> io::Result<HashMap<String, EntryCheck>> {
+    let mut zip = ZipArchive::new(volume::open(&result.path)?)?;
+
+    let mut entries = HashMap::new();
+    for file in &result.files {
+        let password = result.password.as_ref().map(Password::as_bytes);
+        let entry = match password {
+            Some(password) => zip.by_name_decrypt(&file.name, password),
+            None => zip.by_name(&file.name),
+        };
+        let check = match entry {
+            // reading the entry to the end makes the zip reader check its CRC
+            Ok(mut entry) => {
+                let stored_crc = entry.crc32();
+                //AES encrypted entries may leave out the CRC (AE-2), reading
+                //them checks their authentication code instead
+                let encrypted = entry.encrypted();
+                match checksum(&mut entry) {
+                    Ok((size, crc)) if crc == stored_crc || encrypted => Ok((size, crc)),
+                    Ok(_) => Err("entry doesn't match its stored checksum".to_string()),
+                    Err(err) => Err(format!("entry can't be read: {}", err)),
+                }
+            }
+            Err(err) => Err(format!("entry can't be read: {}", err)),
+        };
+        entries.insert(file.name.clone(), check);
+    }
+    Ok(entries)
+}
+
+fn read_tar_entries(result: &ArchiveResult) -> io::Result<HashMap<String, EntryCheck>> {
+    let reader = format::tar_reader(result.format, volume::open(&result.path)?)?;
+    let mut tar = tar::Archive::new(reader);
+
+    let mut entries = HashMap::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().replace('\\', "/");
+        let check = checksum(&mut entry)?;
+        entries.insert(name, Ok(check));
+    }
+    Ok(entries)
+}
+
+/// Size and CRC-32 of everything `reader` returns.
+fn checksum(mut reader: impl Read) -> io::Result<(u64, u32)> {
+    let mut hasher = Hasher::new();
+    let mut size = 0;
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok((size, hasher.finalize()))
+}
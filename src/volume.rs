@@ -0,0 +1,926 @@
+//! Archives split into volumes of a maximum size.
+//!
+//! Zip archives become standard split zips: `name.z01`, `name.z02`, ... and
+//! `name.zip` as the last volume, which zip tools that support split
+//! archives can read. Tarballs and archives encrypted to public keys are cut
+//! into numbered parts `name.tar.gz.001`, `name.tar.gz.002`, ... that only
+//! have to be joined (`cat name.tar.gz.* > name.tar.gz`). Entries larger than a volume simply
+//! continue in the next one.
+//!
+//! Archives are written straight into their volumes by a [`VolumeWriter`],
+//! so a split archive never exists as a whole, and [`open`] reads the
+//! volumes one after another. A split archive is always named by its path
+//! without the volume ending, e.g. `name.zip` or `name.tar.gz`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::format::{self, Format};
+
+/// Smallest volume size, so the end of the central directory of a zip fits
+/// into its last volume.
+pub const MIN_VOLUME_SIZE: u64 = 64 * 1024;
+
+const SPLIT_SIGNATURE: u32 = 0x0807_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+/// Length of the end of central directory record without the comment.
+const END_LEN: usize = 22;
+const ZIP64_LOCATOR_LEN: usize = 20;
+
+/// The volumes of `archive` in order, or just `archive` if it isn't split.
+pub fn volumes(archive: &Path) -> Vec<PathBuf> {
+    let name = archive.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let mut volumes = Vec::new();
+    match Format::from_path(archive) {
+        Some(Format::Zip) if !format::is_encrypted(archive) => {
+            let stem = &name[..name.len() - ".zip".len()];
+            loop {
+                let volume = archive.with_file_name(zip_volume_name(stem, volumes.len()));
+                if !volume.is_file() {
+                    break;
+                }
+                volumes.push(volume);
+            }
+            volumes.push(archive.to_path_buf());
+        }
+        Some(_) if !archive.exists() => {
+            loop {
+                let volume = archive.with_file_name(raw_volume_name(&name, volumes.len()));
+                if !volume.is_file() {
+                    break;
+                }
+                volumes.push(volume);
+            }
+            if volumes.is_empty() {
+                volumes.push(archive.to_path_buf());
+            }
+        }
+        _ => volumes.push(archive.to_path_buf()),
+    }
+    volumes
+}
+
+/// The archive a volume belongs to: `name.zip` for `name.z01`,
+/// `name.tar.gz` for `name.tar.gz.001` and `name.zip.age` for
+/// `name.zip.age.001`. Other paths are returned as they are.
+pub fn archive_of(volume: &Path) -> PathBuf {
+    let name = volume.file_name().unwrap_or_default().to_string_lossy().to_string();
+    if let Some((stem, number)) = name.rsplit_once('.') {
+        let digits = number.trim_start_matches(['z', 'Z']);
+        if digits.len() >= 2 && digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            if digits.len() == number.len() {
+                if is_raw_split(Path::new(stem)) {
+                    return volume.with_file_name(stem);
+                }
+            } else if number.len() == digits.len() + 1 && Format::from_path(Path::new(stem)).is_none() {
+                return volume.with_file_name(format!("{}.zip", stem));
+            }
+        }
+    }
+    volume.to_path_buf()
+}
+
+/// Total size of all volumes of `archive`.
+pub fn total_size(archive: &Path) -> io::Result<u64> {
+    volumes(archive)
+        .iter()
+        .map(|volume| fs::metadata(volume).map(|metadata| metadata.len()))
+        .sum()
+}
+
+/// Opens `archive` for reading. The volumes of a split archive are read one
+/// after another, as if they were a single file.
+pub fn open(archive: &Path) -> io::Result<Volumes> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for volume in volumes(archive) {
+        let file = File::open(&volume)?;
+        let len = file.metadata()?.len();
+        parts.push(Part { file, start, len });
+        start += len;
+    }
+    let mut reader = Volumes {
+        parts,
+        skip: 0,
+        len: start,
+        position: 0,
+        tail: None,
+    };
+
+    if reader.parts.len() > 1 && !is_raw_split(archive) {
+        //the signature in front of the first volume of a split zip is
+        //skipped, which gives back the zip as it was before splitting
+        let mut signature = [0; 4];
+        if reader.read_exact(&mut signature).is_ok() && u32::from_le_bytes(signature) == SPLIT_SIGNATURE {
+            reader.skip = 4;
+            reader.len -= 4;
+        }
+        reader.join_zip()?;
+        reader.position = 0;
+    }
+    Ok(reader)
+}
+
+/// Creates a new archive or volume, failing if the file already exists, so
+/// an archive is never overwritten.
+pub(crate) fn create_new(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).create_new(true).open(path)
+}
+
+/// Whether `archive` is split into plain parts instead of a split zip.
+fn is_raw_split(archive: &Path) -> bool {
+    Format::from_path(archive).is_some_and(|format| format.is_tar()) || format::is_encrypted(archive)
+}
+
+fn zip_volume_name(stem: &str, index: usize) -> String {
+    format!("{}.z{:02}", stem, index + 1)
+}
+
+fn raw_volume_name(name: &str, index: usize) -> String {
+    format!("{}.{:03}", name, index + 1)
+}
+
+/// Where a position of a split zip lands: volumes are filled up to the
+/// volume size, except that the end records are never split but moved into
+/// a volume of their own.
+struct Layout {
+    volume_size: u64,
+    /// Start of the end records, if they would otherwise cross volumes.
+    end_volume: Option<u64>,
+}
+
+impl Layout {
+    /// The volume and the offset in it of `position`.
+    fn locate(&self, position: u64) -> (u64, u64) {
+        match self.end_volume {
+            Some(start) if position >= start => (start / self.volume_size + 1, position - start),
+            _ => (position / self.volume_size, position % self.volume_size),
+        }
+    }
+
+    /// Where the volume holding `position` ends.
+    fn volume_end(&self, position: u64) -> u64 {
+        let end = (position / self.volume_size + 1).saturating_mul(self.volume_size);
+        match self.end_volume {
+            Some(start) if position >= start => u64::MAX,
+            Some(start) if start < end => start,
+            _ => end,
+        }
+    }
+}
+
+/// How a [`VolumeWriter`] lays out the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Split {
+    /// Not split, the archive is written as it is.
+    Whole,
+    /// A split zip, see [`volume`](self).
+    Zip,
+    /// Numbered parts.
+    Raw,
+}
+
+/// Writes an archive straight into its volumes, or into the archive itself
+/// if it isn't split.
+///
+/// The name of the archive is taken when the writer is created, and no
+/// existing file is ever overwritten. Dropping the writer before
+/// [`finish`](VolumeWriter::finish) removes everything it wrote.
+pub(crate) struct VolumeWriter {
+    archive: PathBuf,
+    split: Split,
+    layout: Layout,
+    volumes: Vec<(PathBuf, File)>,
+    /// Bytes in front of the archive in the first volume, the split
+    /// signature of a split zip.
+    skip: u64,
+    position: u64,
+    len: u64,
+    finished: bool,
+}
+
+impl VolumeWriter {
+    /// Creates `archive`, split into volumes of at most `volume_size` bytes
+    /// if a size is given. Tarballs and archives encrypted to public keys
+    /// are cut into parts, zips become split zips.
+    pub(crate) fn create(archive: &Path, volume_size: Option<u64>) -> io::Result<VolumeWriter> {
+        if volume_size.is_some_and(|volume_size| volume_size < MIN_VOLUME_SIZE) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("volumes must hold at least {} bytes", MIN_VOLUME_SIZE),
+            ));
+        }
+        let file = create_new(archive)?;
+        let mut writer = VolumeWriter {
+            archive: archive.to_path_buf(),
+            split: Split::Whole,
+            layout: Layout {
+                volume_size: volume_size.unwrap_or(u64::MAX),
+                end_volume: None,
+            },
+            volumes: Vec::new(),
+            skip: 0,
+            position: 0,
+            len: 0,
+            finished: false,
+        };
+        if volume_size.is_none() {
+            writer.volumes.push((archive.to_path_buf(), file));
+        } else if is_raw_split(archive) {
+            //the empty archive keeps the name taken until the volumes are done
+            writer.split = Split::Raw;
+        } else {
+            writer.split = Split::Zip;
+            writer.write_at(0, &SPLIT_SIGNATURE.to_le_bytes())?;
+            writer.skip = 4;
+        }
+        Ok(writer)
+    }
+
+    /// Completes the archive, writes it to disk and returns its volumes in
+    /// order. A split archive that fits into one volume is written as a
+    /// single file.
+    pub(crate) fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        match self.split {
+            Split::Whole => {}
+            Split::Raw if self.volumes.len() > 1 => fs::remove_file(&self.archive)?,
+            Split::Raw if !self.volumes.is_empty() => self.rename_last()?,
+            Split::Raw => self.volumes.push((self.archive.clone(), File::open(&self.archive)?)),
+            Split::Zip if self.volumes.len() > 1 => {
+                self.finish_zip()?;
+                self.rename_last()?;
+            }
+            Split::Zip => {
+                self.unsplit()?;
+                self.rename_last()?;
+            }
+        }
+        for (_, file) in &self.volumes {
+            file.sync_all()?;
+        }
+        self.finished = true;
+        Ok(self.volumes.iter().map(|(path, _)| path.clone()).collect())
+    }
+
+    /// Writes `buf` at `physical`, the position counting the bytes in front
+    /// of the archive, up to the end of the volume it lands in.
+    fn write_at(&mut self, physical: u64, buf: &[u8]) -> io::Result<usize> {
+        let (volume, offset) = self.layout.locate(physical);
+        while self.volumes.len() as u64 <= volume {
+            let path = self.volume_name(self.volumes.len());
+            let file = create_new(&path)?;
+            self.volumes.push((path, file));
+        }
+        let room = self.layout.volume_end(physical) - physical;
+        let len = buf.len().min(usize::try_from(room).unwrap_or(usize::MAX));
+        let file = &mut self.volumes[volume as usize].1;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write(&buf[..len])
+    }
+
+    fn volume_name(&self, index: usize) -> PathBuf {
+        let name = self.archive.file_name().unwrap_or_default().to_string_lossy().to_string();
+        match self.split {
+            Split::Zip => self.archive.with_file_name(zip_volume_name(&name[..name.len() - ".zip".len()], index)),
+            _ => self.archive.with_file_name(raw_volume_name(&name, index)),
+        }
+    }
+
+    /// Gives the last volume the name of the archive.
+    fn rename_last(&mut self) -> io::Result<()> {
+        if let Some((path, _)) = self.volumes.last_mut() {
+            fs::rename(&*path, &self.archive)?;
+            *path = self.archive.clone();
+        }
+        Ok(())
+    }
+
+    /// Turns a split zip that fits into one volume into a plain zip, by
+    /// dropping the split signature in front of it.
+    fn unsplit(&mut self) -> io::Result<()> {
+        let file = &mut self.volumes[0].1;
+        let mut buffer = vec![0; 64 * 1024];
+        let mut position = 0;
+        while position < self.len {
+            let len = (self.len - position).min(buffer.len() as u64) as usize;
+            file.seek(SeekFrom::Start(position + self.skip))?;
+            file.read_exact(&mut buffer[..len])?;
+            file.seek(SeekFrom::Start(position))?;
+            file.write_all(&buffer[..len])?;
+            position += len as u64;
+        }
+        file.set_len(self.len)
+    }
+
+    /// Turns the written zip into a split zip: the central directory and the
+    /// end records are rewritten to point into the volumes, and the end
+    /// records are moved into a volume of their own if they would cross
+    /// volumes.
+    fn finish_zip(&mut self) -> io::Result<()> {
+        let size = self.len;
+        let (central_start, mut tail) = read_tail(self, size, |_, offset| Ok(offset))?;
+        let ends = EndRecords::find(&tail, central_start, |_, offset| Ok(offset))?;
+
+        //positions in the volumes count the split signature
+        let skip = self.skip;
+        let volume_size = self.layout.volume_size;
+        let end_start = central_start + ends.start as u64 + skip;
+        let total = size + skip;
+        let mut layout = Layout {
+            volume_size,
+            end_volume: None,
+        };
+        if end_start / volume_size != (total - 1) / volume_size {
+            layout.end_volume = Some(end_start);
+        }
+        let last = layout.locate(total - 1).0;
+        let disk = |volume: u64| u32::try_from(volume).map_err(|_| invalid("too many volumes"));
+
+        let records = rewrite_entries(&mut tail[..ends.central_len()], ends.entries, |offset, _| {
+            let (volume, offset) = layout.locate(offset + skip);
+            Ok((offset, disk(volume)?))
+        })?;
+        let central = layout.locate(central_start + skip);
+        let on_last = records
+            .iter()
+            .filter(|&&record| layout.locate(central_start + record as u64 + skip).0 == last)
+            .count() as u64;
+        ends.rewrite(
+            &mut tail,
+            EndValues {
+                disk: disk(last)?,
+                central_disk: disk(central.0)?,
+                entries_on_disk: on_last,
+                central_offset: central.1,
+                zip64_offset: ends.zip64.map(|zip64| layout.locate(central_start + zip64 as u64 + skip).1),
+                disks: disk(last + 1)?,
+            },
+        )?;
+
+        //the tail is written again where the new layout puts it
+        self.truncate(central_start)?;
+        self.layout = layout;
+        self.seek(SeekFrom::Start(central_start))?;
+        self.write_all(&tail)
+    }
+
+    /// Cuts the archive off at `len`, removing the volumes after it.
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        let (volume, offset) = self.layout.locate(len + self.skip);
+        let keep = (volume as usize + 1).min(self.volumes.len());
+        for (path, file) in self.volumes.split_off(keep) {
+            drop(file);
+            fs::remove_file(path)?;
+        }
+        if let Some((_, file)) = self.volumes.get(volume as usize) {
+            file.set_len(offset)?;
+        }
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.write_at(self.position + self.skip, buf)?;
+        self.position += written as u64;
+        self.len = self.len.max(self.position);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.volumes.iter_mut().try_for_each(|(_, file)| file.flush())
+    }
+}
+
+impl Read for VolumeWriter {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len {
+            return Ok(0);
+        }
+        let physical = self.position + self.skip;
+        let (volume, offset) = self.layout.locate(physical);
+        let room = (self.layout.volume_end(physical) - physical).min(self.len - self.position);
+        let len = buf.len().min(usize::try_from(room).unwrap_or(usize::MAX));
+        let (_, file) = self
+            .volumes
+            .get_mut(volume as usize)
+            .ok_or_else(|| invalid("volume is missing"))?;
+        file.seek(SeekFrom::Start(offset))?;
+        let read = file.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for VolumeWriter {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.position = seek_position(position, self.position, self.len)?;
+        Ok(self.position)
+    }
+}
+
+impl Drop for VolumeWriter {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        for (path, _) in self.volumes.drain(..) {
+            let _ = fs::remove_file(path);
+        }
+        let _ = fs::remove_file(&self.archive);
+    }
+}
+
+/// An archive opened by [`open`], reading all its volumes as one file.
+#[derive(Debug)]
+pub struct Volumes {
+    parts: Vec<Part>,
+    /// Bytes in front of the archive in the first volume, the split
+    /// signature of a split zip.
+    skip: u64,
+    len: u64,
+    position: u64,
+    /// The end of a split zip as it is in a plain zip: where it starts and
+    /// its bytes.
+    tail: Option<(u64, Vec<u8>)>,
+}
+
+#[derive(Debug)]
+struct Part {
+    file: File,
+    /// Position of the volume in all volumes joined.
+    start: u64,
+    len: u64,
+}
+
+impl Volumes {
+    /// Makes the end of a split zip read like the end of a plain zip, so
+    /// the offsets of the central directory point into the joined volumes.
+    fn join_zip(&mut self) -> io::Result<()> {
+        let starts: Vec<u64> = self.parts.iter().map(|part| part.start).collect();
+        let skip = self.skip;
+        let position = |disk: u32, offset: u64| -> io::Result<u64> {
+            let start = starts.get(disk as usize).ok_or_else(|| invalid("entry is on a missing volume"))?;
+            (start + offset)
+                .checked_sub(skip)
+                .ok_or_else(|| invalid("entry is in front of the first volume"))
+        };
+
+        let (central_start, mut tail) = read_tail(self, self.len, position)?;
+        let ends = EndRecords::find(&tail, central_start, position)?;
+
+        rewrite_entries(&mut tail[..ends.central_len()], ends.entries, |offset, disk| {
+            Ok((position(disk, offset)?, 0))
+        })?;
+        ends.rewrite(
+            &mut tail,
+            EndValues {
+                disk: 0,
+                central_disk: 0,
+                entries_on_disk: ends.entries,
+                central_offset: central_start,
+                zip64_offset: ends.zip64.map(|zip64| central_start + zip64 as u64),
+                disks: 1,
+            },
+        )?;
+        self.tail = Some((central_start, tail));
+        Ok(())
+    }
+}
+
+impl Read for Volumes {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let mut end = self.len;
+        if let Some((start, tail)) = &self.tail {
+            if self.position >= *start {
+                let rest = &tail[(self.position - start) as usize..];
+                let len = rest.len().min(buf.len());
+                buf[..len].copy_from_slice(&rest[..len]);
+                self.position += len as u64;
+                return Ok(len);
+            }
+            end = *start;
+        }
+
+        let physical = self.position + self.skip;
+        let index = self.parts.partition_point(|part| part.start + part.len <= physical);
+        let part = &mut self.parts[index];
+        let room = (part.start + part.len - physical).min(end - self.position);
+        let len = buf.len().min(usize::try_from(room).unwrap_or(usize::MAX));
+        part.file.seek(SeekFrom::Start(physical - part.start))?;
+        let read = part.file.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for Volumes {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.position = seek_position(position, self.position, self.len)?;
+        Ok(self.position)
+    }
+}
+
+fn seek_position(position: SeekFrom, current: u64, len: u64) -> io::Result<u64> {
+    match position {
+        SeekFrom::Start(position) => Some(position),
+        SeekFrom::End(offset) => len.checked_add_signed(offset),
+        SeekFrom::Current(offset) => current.checked_add_signed(offset),
+    }
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))
+}
+
+/// Reads everything from the start of the central directory to the end of a
+/// zip of `size` bytes. `position` turns a disk and offset of the end records
+/// into a position in `file`. Returns the start and the bytes.
+fn read_tail(file: &mut (impl Read + Seek), size: u64, position: impl Fn(u32, u64) -> io::Result<u64>) -> io::Result<(u64, Vec<u8>)> {
+    let search = size.min((END_LEN + u16::MAX as usize + ZIP64_LOCATOR_LEN) as u64);
+    let mut end = vec![0; search as usize];
+    file.seek(SeekFrom::Start(size - search))?;
+    file.read_exact(&mut end)?;
+
+    let start = EndRecords::find(&end, size - search, &position)?.central_start;
+    let mut tail = vec![0; (size - start) as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut tail)?;
+    Ok((start, tail))
+}
+
+/// The end records of a zip, found in a buffer that starts at the position
+/// `buffer_start` of the zip.
+struct EndRecords {
+    /// Index of the first end record.
+    start: usize,
+    end: usize,
+    zip64: Option<usize>,
+    locator: Option<usize>,
+    entries: u64,
+    /// Position of the central directory in the zip.
+    central_start: u64,
+    /// Index of the central directory in the buffer, if it starts in it.
+    central: Option<usize>,
+}
+
+/// The disk numbers and offsets written into the end records.
+struct EndValues {
+    disk: u32,
+    central_disk: u32,
+    entries_on_disk: u64,
+    central_offset: u64,
+    zip64_offset: Option<u64>,
+    disks: u32,
+}
+
+impl EndRecords {
+    fn find(buffer: &[u8], buffer_start: u64, position: impl Fn(u32, u64) -> io::Result<u64>) -> io::Result<EndRecords> {
+        //the end record is found from the back, it may be followed by a comment
+        let end = (0..=buffer.len().saturating_sub(END_LEN))
+            .rev()
+            .find(|&index| {
+                read_u32(buffer, index) == END_SIGNATURE && index + END_LEN + read_u16(buffer, index + 20) as usize == buffer.len()
+            })
+            .ok_or_else(|| invalid("end of central directory not found"))?;
+
+        let mut records = EndRecords {
+            start: end,
+            end,
+            zip64: None,
+            locator: None,
+            entries: read_u16(buffer, end + 10) as u64,
+            central_start: 0,
+            central: None,
+        };
+        let mut central_disk = read_u16(buffer, end + 6) as u32;
+        let mut central_offset = read_u32(buffer, end + 16) as u64;
+
+        if end >= ZIP64_LOCATOR_LEN && read_u32(buffer, end - ZIP64_LOCATOR_LEN) == ZIP64_LOCATOR_SIGNATURE {
+            let locator = end - ZIP64_LOCATOR_LEN;
+            let zip64 = position(read_u32(buffer, locator + 4), read_u64(buffer, locator + 8))?
+                .checked_sub(buffer_start)
+                .map(|zip64| zip64 as usize)
+                .filter(|&zip64| zip64 + 56 <= locator && read_u32(buffer, zip64) == ZIP64_END_SIGNATURE)
+                .ok_or_else(|| invalid("zip64 end of central directory not found"))?;
+            records.start = zip64;
+            records.zip64 = Some(zip64);
+            records.locator = Some(locator);
+            records.entries = read_u64(buffer, zip64 + 32);
+            central_disk = read_u32(buffer, zip64 + 20);
+            central_offset = read_u64(buffer, zip64 + 48);
+        }
+
+        records.central_start = position(central_disk, central_offset)?;
+        records.central = records.central_start.checked_sub(buffer_start).map(|central| central as usize);
+        Ok(records)
+    }
+
+    /// Length of the central directory in a buffer that starts with it.
+    fn central_len(&self) -> usize {
+        self.start - self.central.unwrap_or(0)
+    }
+
+    /// Writes `values` into the end records in `buffer`.
+    fn rewrite(&self, buffer: &mut [u8], values: EndValues) -> io::Result<()> {
+        let end = self.end;
+        if let (Some(zip64), Some(locator)) = (self.zip64, self.locator) {
+            write_u32(buffer, zip64 + 16, values.disk);
+            write_u32(buffer, zip64 + 20, values.central_disk);
+            write_u64(buffer, zip64 + 24, values.entries_on_disk);
+            write_u64(buffer, zip64 + 48, values.central_offset);
+            write_u32(buffer, locator + 4, values.disk);
+            write_u64(buffer, locator + 8, values.zip64_offset.unwrap_or_default());
+            write_u32(buffer, locator + 16, values.disks);
+        }
+
+        //fields that don't fit are left at their maximum, the zip64 record
+        //holds them
+        write_u16_field(buffer, end + 4, values.disk as u64)?;
+        write_u16_field(buffer, end + 6, values.central_disk as u64)?;
+        write_u16_field(buffer, end + 8, values.entries_on_disk)?;
+        write_u32_field(buffer, end + 16, values.central_offset)
+    }
+}
+
+/// Rewrites the local header offset and start disk of the `count` entries
+/// of the central directory `central` with `relocate`. Returns the index of
+/// every entry.
+fn rewrite_entries(central: &mut [u8], count: u64, relocate: impl Fn(u64, u32) -> io::Result<(u64, u32)>) -> io::Result<Vec<usize>> {
+    let mut records = Vec::new();
+    let mut index = 0;
+    for _ in 0..count {
+        if index + 46 > central.len() || read_u32(central, index) != CENTRAL_HEADER_SIGNATURE {
+            return Err(invalid("invalid central directory"));
+        }
+        let name_len = read_u16(central, index + 28) as usize;
+        let extra_len = read_u16(central, index + 30) as usize;
+        let comment_len = read_u16(central, index + 32) as usize;
+        let record_len = 46 + name_len + extra_len + comment_len;
+        if index + record_len > central.len() {
+            return Err(invalid("invalid central directory"));
+        }
+
+        //values that don't fit into their field are in the zip64 extra field,
+        //in this order
+        let mut offset = read_u32(central, index + 42) as u64;
+        let mut disk = read_u16(central, index + 34) as u32;
+        let mut offset_at = None;
+        let mut disk_at = None;
+        let mut extra = index + 46 + name_len;
+        let extra_end = extra + extra_len;
+        while extra + 4 <= extra_end {
+            let id = read_u16(central, extra);
+            let len = read_u16(central, extra + 2) as usize;
+            if id == ZIP64_EXTRA_ID {
+                let mut field = extra + 4;
+                if read_u32(central, index + 24) == 0xffff_ffff {
+                    field += 8;
+                }
+                if read_u32(central, index + 20) == 0xffff_ffff {
+                    field += 8;
+                }
+                if offset == 0xffff_ffff && field + 8 <= extra + 4 + len {
+                    offset = read_u64(central, field);
+                    offset_at = Some(field);
+                    field += 8;
+                }
+                if disk == 0xffff && field + 4 <= extra + 4 + len {
+                    disk = read_u32(central, field);
+                    disk_at = Some(field);
+                }
+            }
+            extra += 4 + len;
+        }
+
+        let (new_offset, new_disk) = relocate(offset, disk)?;
+        match offset_at {
+            Some(field) => write_u64(central, field, new_offset),
+            None => write_u32_field(central, index + 42, new_offset)?,
+        }
+        match disk_at {
+            Some(field) => write_u32(central, field, new_disk),
+            None => write_u16_field(central, index + 34, new_disk as u64)?,
+        }
+        records.push(index);
+        index += record_len;
+    }
+    Ok(records)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u16(buffer: &[u8], index: usize) -> u16 {
+    buffer
+        .get(index..index + 2)
+        .map_or(0, |bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(buffer: &[u8], index: usize) -> u32 {
+    buffer
+        .get(index..index + 4)
+        .map_or(0, |bytes| u32::from_le_bytes(bytes.try_into().unwrap_or_default()))
+}
+
+fn read_u64(buffer: &[u8], index: usize) -> u64 {
+    buffer
+        .get(index..index + 8)
+        .map_or(0, |bytes| u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+}
+
+fn write_u32(buffer: &mut [u8], index: usize, value: u32) {
+    buffer[index..index + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u64(buffer: &mut [u8], index: usize, value: u64) {
+    buffer[index..index + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Writes a 16 bit field, unless it holds the zip64 marker.
+fn write_u16_field(buffer: &mut [u8], index: usize, value: u64) -> io::Result<()> {
+    if read_u16(buffer, index) == 0xffff {
+        return Ok(());
+    }
+    let value = u16::try_from(value)
+        .ok()
+        .filter(|&value| value != 0xffff)
+        .ok_or_else(|| invalid("too many volumes"))?;
+    buffer[index..index + 2].copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+/// Writes a 32 bit field, unless it holds the zip64 marker.
+fn write_u32_field(buffer: &mut [u8], index: usize, value: u64) -> io::Result<()> {
+    if read_u32(buffer, index) == 0xffff_ffff {
+        return Ok(());
+    }
+    let value = u32::try_from(value)
+        .ok()
+        .filter(|&value| value != 0xffff_ffff)
+        .ok_or_else(|| invalid("offset doesn't fit into the zip"))?;
+    write_u32(buffer, index, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+    use super::*;
+
+    /// Writes a zip with a stored entry of `len` bytes into volumes.
+    fn write_zip(archive: &Path, len: usize, volume_size: Option<u64>) -> Vec<PathBuf> {
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut zip = ZipWriter::new(VolumeWriter::create(archive, volume_size).unwrap());
+        zip.start_file("a", options).unwrap();
+        zip.write_all(&contents(len)).unwrap();
+        zip.start_file("b", options).unwrap();
+        zip.write_all(b"second").unwrap();
+        zip.finish().unwrap().finish().unwrap()
+    }
+
+    fn contents(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index % 251) as u8).collect()
+    }
+
+    fn read_entry(archive: &Path, name: &str) -> Vec<u8> {
+        let mut zip = ZipArchive::new(open(archive).unwrap()).unwrap();
+        let mut entry = zip.by_name(name).unwrap();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn layout_fills_volumes() {
+        let layout = Layout {
+            volume_size: 100,
+            end_volume: None,
+        };
+        assert_eq!(layout.locate(0), (0, 0));
+        assert_eq!(layout.locate(250), (2, 50));
+        assert_eq!(layout.volume_end(250), 300);
+    }
+
+    #[test]
+    fn layout_moves_end_records() {
+        let layout = Layout {
+            volume_size: 100,
+            end_volume: Some(290),
+        };
+        assert_eq!(layout.locate(289), (2, 89));
+        assert_eq!(layout.volume_end(250), 290);
+        assert_eq!(layout.locate(290), (3, 0));
+        assert_eq!(layout.locate(310), (3, 20));
+        assert_eq!(layout.volume_end(310), u64::MAX);
+    }
+
+    #[test]
+    fn volume_names() {
+        assert_eq!(archive_of(Path::new("a/name.z01")), Path::new("a/name.zip"));
+        assert_eq!(archive_of(Path::new("name.tar.gz.002")), Path::new("name.tar.gz"));
+        assert_eq!(archive_of(Path::new("name.zip.age.001")), Path::new("name.zip.age"));
+        assert_eq!(archive_of(Path::new("name.zip")), Path::new("name.zip"));
+        assert_eq!(archive_of(Path::new("report.001")), Path::new("report.001"));
+    }
+
+    #[test]
+    fn split_zip_round_trip() {
+        let folder = tempfile::tempdir().unwrap();
+        let archive = folder.path().join("name.zip");
+        let volumes = write_zip(&archive, 200_000, Some(MIN_VOLUME_SIZE));
+
+        assert_eq!(volumes.len(), 4);
+        assert_eq!(volumes[0], folder.path().join("name.z01"));
+        assert_eq!(volumes[3], archive);
+        assert_eq!(super::volumes(&archive), volumes);
+        assert_eq!(fs::metadata(&volumes[0]).unwrap().len(), MIN_VOLUME_SIZE);
+        assert_eq!(&fs::read(&volumes[0]).unwrap()[..4], &SPLIT_SIGNATURE.to_le_bytes());
+        assert_eq!(read_entry(&archive, "a"), contents(200_000));
+        assert_eq!(read_entry(&archive, "b"), b"second");
+    }
+
+    #[test]
+    fn end_records_never_cross_volumes() {
+        //the end records cross the first volume boundary for some of these
+        //sizes and are moved into a volume of their own
+        let mut moved = false;
+        for len in 65_300..65_400 {
+            let folder = tempfile::tempdir().unwrap();
+            let archive = folder.path().join("name.zip");
+            let volumes = write_zip(&archive, len, Some(MIN_VOLUME_SIZE));
+            for volume in &volumes {
+                assert!(fs::metadata(volume).unwrap().len() <= MIN_VOLUME_SIZE);
+            }
+            assert_eq!(read_entry(&archive, "a"), contents(len), "entry of {} bytes", len);
+            assert_eq!(read_entry(&archive, "b"), b"second");
+            moved |= fs::metadata(&archive).unwrap().len() == END_LEN as u64;
+        }
+        assert!(moved);
+    }
+
+    #[test]
+    fn small_split_zip_is_plain() {
+        let folder = tempfile::tempdir().unwrap();
+        let archive = folder.path().join("name.zip");
+        let volumes = write_zip(&archive, 1000, Some(MIN_VOLUME_SIZE));
+
+        assert_eq!(volumes, vec![archive.clone()]);
+        let bytes = fs::read(&archive).unwrap();
+        assert_eq!(&bytes[..4], b"PK\x03\x04");
+        let mut zip = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(zip.by_name("a").unwrap().size(), 1000);
+    }
+
+    #[test]
+    fn raw_parts_round_trip() {
+        let folder = tempfile::tempdir().unwrap();
+        let archive = folder.path().join("name.tar.gz");
+        let mut writer = VolumeWriter::create(&archive, Some(MIN_VOLUME_SIZE)).unwrap();
+        writer.write_all(&contents(150_000)).unwrap();
+        let volumes = writer.finish().unwrap();
+
+        assert_eq!(volumes.len(), 3);
+        assert_eq!(volumes[2], folder.path().join("name.tar.gz.003"));
+        assert!(!archive.exists());
+        assert_eq!(archive_of(&volumes[1]), archive);
+        assert_eq!(total_size(&archive).unwrap(), 150_000);
+
+        let mut reader = open(&archive).unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, contents(150_000));
+        reader.seek(SeekFrom::Start(MIN_VOLUME_SIZE - 2)).unwrap();
+        let mut across = [0; 4];
+        reader.read_exact(&mut across).unwrap();
+        assert_eq!(&across[..], &contents(150_000)[MIN_VOLUME_SIZE as usize - 2..][..4]);
+    }
+
+    #[test]
+    fn never_overwrites() {
+        let folder = tempfile::tempdir().unwrap();
+        let archive = folder.path().join("name.tar.gz");
+        fs::write(folder.path().join("name.tar.gz.002"), b"keep").unwrap();
+
+        let mut writer = VolumeWriter::create(&archive, Some(MIN_VOLUME_SIZE)).unwrap();
+        assert!(writer.write_all(&contents(100_000)).is_err());
+        drop(writer);
+
+        //everything written is removed again, the existing file is kept
+        assert!(!archive.exists());
+        assert!(!folder.path().join("name.tar.gz.001").exists());
+        assert_eq!(fs::read(folder.path().join("name.tar.gz.002")).unwrap(), b"keep");
+        assert!(VolumeWriter::create(&folder.path().join("name.tar.gz.002"), None).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+//! Watch mode: a long-running process that archives the files of profiles as
+//! they arrive, woken up by filesystem events (inotify on Linux).
+//!
+//! Nothing is kept in memory between checks: which files are ready is decided
+//! from their modification times, and files that are already in the catalog
+//! are never archived again, so the watcher can be restarted at any time.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Local};
+use notify::{RecursiveMode, Watcher};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use the_archivist::cleanup::{self, Outcome};
+use the_archivist::{ArchiveReport, Archiver, Catalog, Compression, CompressionPolicy, FileEntry, Format, Password, Recipient};
+
+use crate::cli::AfterArchive;
+use crate::profile::Profile;
+
+/// Time without events before a folder is checked, so a burst of events
+/// (e.g. a file being copied) only causes one check.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Folders are checked at least this often, also without events.
+const RESCAN: Duration = Duration::from_secs(60);
+
+/// How the archives of all watched profiles are written; the options of
+/// the command line win over the ones of the profiles.
+pub struct WatchOptions {
+    pub compression: Option<Compression>,
+    pub format: Option<Format>,
+    pub volume_size: Option<u64>,
+    /// Encrypt the archives of all profiles, not only of the ones asking
+    /// for it.
+    pub encrypt: bool,
+    pub password: Option<Password>,
+    /// Public keys to encrypt the archives of all profiles to, instead of
+    /// the ones of the profiles.
+    pub recipients: Vec<Recipient>,
+    pub jobs: Option<usize>,
+    pub policy: CompressionPolicy,
+}
+
+/// A profile being watched.
+struct Watched {
+    profile: Profile,
+    folder: PathBuf,
+    archive_folder: PathBuf,
+    /// Time of the last event, if there were events since the last check.
+    changed: Option<Instant>,
+    next_check: Instant,
+}
+
+/// Watches the folders of `profiles` until SIGTERM or SIGINT, archiving
+/// their files whenever they are ready.
+pub fn watch(profiles: Vec<Profile>, options: &WatchOptions) -> Result<(), String> {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&stop)).map_err(|err| format!("could not handle signals: {}", err))?;
+    }
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(|err| format!("could not start watching: {}", err))?;
+
+    let mut watched = Vec::new();
+    for profile in profiles {
+        let folder = profile
+            .folder
+            .canonicalize()
+            .map_err(|err| format!("{}: {}", profile.folder.display(), err))?;
+        let mode = if profile.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        watcher
+            .watch(&folder, mode)
+            .map_err(|err| format!("could not watch {}: {}", folder.display(), err))?;
+        log(&profile.name, &format!("watching \x1b[96m{}\x1b[37m", folder.display()));
+
+        let archive_folder = profile
+            .destination
+            .as_ref()
+            .map(|destination| destination.canonicalize().unwrap_or_else(|_| destination.clone()))
+            .unwrap_or_else(|| folder.join(the_archivist::scanner::ARCHIVE_FOLDER));
+        watched.push(Watched {
+            profile,
+            folder,
+            archive_folder,
+            changed: None,
+            //files that arrived while the watcher wasn't running are
+            //archived right away
+            next_check: Instant::now(),
+        });
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        for watched in watched.iter_mut().filter(|watched| watched.due() <= now) {
+            watched.check(options);
+        }
+
+        //wake up at least every second to notice the stop signal
+        let next = watched.iter().map(Watched::due).min().unwrap_or(now + RESCAN);
+        let timeout = next.saturating_duration_since(Instant::now()).min(Duration::from_secs(1));
+        match events.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                for watched in &mut watched {
+                    let relevant = event
+                        .paths
+                        .iter()
+                        .any(|path| path.starts_with(&watched.folder) && !path.starts_with(&watched.archive_folder));
+                    if relevant {
+                        watched.changed = Some(Instant::now());
+                    }
+                }
+            }
+            Ok(Err(err)) => eprintln!("\x1b[31mError watching: {}\x1b[37m", err),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    println!("-----------------------------------------------");
+    println!("Stopped watching");
+    println!("-----------------------------------------------");
+    Ok(())
+}
+
+impl Watched {
+    /// When the profile has to be checked next.
+    fn due(&self) -> Instant {
+        match self.changed {
+            Some(changed) => (changed + DEBOUNCE).min(self.next_check),
+            None => self.next_check,
+        }
+    }
+
+    /// Archives the files that are ready and works out when to check again.
+    fn check(&mut self, options: &WatchOptions) {
+        self.changed = None;
+        self.next_check = Instant::now() + RESCAN;
+
+        let scan = match self.profile.scanner().scan() {
+            Ok(scan) => scan,
+            Err(err) => {
+                log_error(&self.profile.name, &format!("error reading directory: {}", err));
+                return;
+            }
+        };
+        let Some(mut plan) = self.profile.plan(&scan) else {
+            return;
+        };
+
+        let catalog = if plan.archive_folder.join(the_archivist::catalog::CATALOG_NAME).is_file() {
+            match Catalog::open(&plan.archive_folder) {
+                Ok(catalog) => Some(catalog),
+                Err(err) => {
+                    log_error(&self.profile.name, &err.to_string());
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        let unchanged_for = self.profile.unchanged_for.unwrap_or(DEBOUNCE);
+        let now = SystemTime::now();
+        let mut archives = Vec::new();
+        for archive in &plan.archives {
+            let mut ready: Vec<(SystemTime, FileEntry)> = Vec::new();
+            for file in &archive.files {
+                let Some(modified) = fs::metadata(&file.path).and_then(|metadata| metadata.modified()).ok() else {
+                    continue;
+                };
+                //files that are in the catalog unchanged and verified were
+                //archived already but kept; files whose entry failed the
+                //verification are archived again
+                let archived = catalog.as_ref().is_some_and(|catalog| {
+                    catalog
+                        .holds(&self.folder.join(&file.name), file.size, DateTime::<Local>::from(modified))
+                        .unwrap_or(false)
+                });
+                if archived {
+                    continue;
+                }
+
+                let age = now.duration_since(modified).unwrap_or_default();
+                if age >= unchanged_for {
+                    ready.push((modified, file.clone()));
+                } else {
+                    if let Some(ready_at) = Instant::now().checked_add(unchanged_for - age) {
+                        self.next_check = self.next_check.min(ready_at);
+                    }
+                }
+            }
+
+            //oldest files first, in full batches only
+            ready.sort_by_key(|(modified, _)| *modified);
+            let files: Vec<FileEntry> = ready.into_iter().map(|(_, file)| file).collect();
+            let batch = self.profile.batch.unwrap_or(files.len().max(1));
+            for chunk in files.chunks(batch).filter(|chunk| chunk.len() == batch) {
+                archives.push(archive.with_files(chunk.to_vec()));
+            }
+        }
+        if archives.is_empty() {
+            return;
+        }
+
+        let format = options.format.or(self.profile.format).unwrap_or_default();
+        plan.archives = archives;
+        let plan = plan.unique_names(format);
+        let archiver = Archiver::new()
+            .compression(options.compression.or(self.profile.compression).unwrap_or_default())
+            .policy(options.policy.clone())
+            .format(format)
+            .volume_size(options.volume_size.or(self.profile.volume_size))
+            .password(options.password.clone().filter(|_| options.encrypt || self.profile.encrypt))
+            .recipients(if options.recipients.is_empty() {
+                self.profile.recipients.clone()
+            } else {
+                options.recipients.clone()
+            })
+            .jobs(options.jobs.or(self.profile.jobs).unwrap_or(0))
+            .catalog(true);
+        let mut report = match archiver.execute(&plan) {
+            Ok(report) => report,
+            Err(err) => {
+                log_error(&self.profile.name, &format!("error creating archive: {}", err));
+                return;
+            }
+        };
+        report.verify();
+        let mut catalog = Catalog::open(&plan.archive_folder)
+            .map_err(|err| log_error(&self.profile.name, &err.to_string()))
+            .ok();
+
+        for archive in &report.archives {
+            let archive = match archive {
+                Ok(archive) => archive,
+                Err(err) => {
+                    log_error(&self.profile.name, &format!("error creating archive: {}", err));
+                    continue;
+                }
+            };
+            for (path, err) in &archive.errors {
+                log_error(&self.profile.name, &format!("error archiving file {}: {}", path.display(), err));
+            }
+            if let Some(Err(err)) = catalog.as_mut().map(|catalog| catalog.record_verification(archive)) {
+                log_error(&self.profile.name, &err.to_string());
+            }
+            let verified = archive.verification.as_ref().map_or(0, |verification| verification.verified.len());
+            log(
+                &self.profile.name,
+                &format!(
+                    "archived [{}] files into \x1b[96m{}\x1b[37m{}, verified [{}/{}]",
+                    archive.files.len(),
+                    archive.path.display(),
+                    if archive.is_encrypted() || archive.has_recipients() { " (encrypted)" } else { "" },
+                    verified,
+                    archive.files.len()
+                ),
+            );
+        }
+
+        self.cleanup(&report);
+        if let Some(retention) = self.profile.retention {
+            match retention.plan(&plan.archive_folder, Local::now()) {
+                Ok(prune_plan) => {
+                    for (path, result) in prune_plan.execute() {
+                        match result {
+                            Ok(()) => log(&self.profile.name, &format!("pruned {}", path.display())),
+                            Err(err) => log_error(&self.profile.name, &format!("error removing archive {}", err)),
+                        }
+                    }
+                }
+                Err(err) => log_error(&self.profile.name, &format!("error pruning the archives: {}", err)),
+            }
+        }
+    }
+
+    /// Removes the archived files, if the profile says so.
+    fn cleanup(&self, report: &ArchiveReport) {
+        let results = match self.profile.after_archive {
+            AfterArchive::Keep => return,
+            AfterArchive::Trash => cleanup::trash_archived(report),
+            AfterArchive::Delete => cleanup::delete_archived(report),
+        };
+        let removed = results
+            .iter()
+            .filter(|result| matches!(result.outcome, Outcome::Deleted | Outcome::Trashed(_)))
+            .count();
+        for result in &results {
+            match &result.outcome {
+                Outcome::Kept(reason) => log_error(&self.profile.name, &format!("kept {} ({})", result.path.display(), reason)),
+                Outcome::Failed(err) => log_error(&self.profile.name, &format!("error removing file {}: {}", result.path.display(), err)),
+                _ => {}
+            }
+        }
+        let action = if self.profile.after_archive == AfterArchive::Trash {
+            "moved to the trash"
+        } else {
+            "deleted"
+        };
+        log(&self.profile.name, &format!("{} [{}/{}] files", action, removed, results.len()));
+    }
+}
+
+fn log(profile: &str, message: &str) {
+    println!("[{}] {}: {}", Local::now().format("%Y-%m-%d %H:%M:%S"), profile, message);
+}
+
+fn log_error(profile: &str, message: &str) {
+    eprintln!("\x1b[31m[{}] {}: {}\x1b[37m", Local::now().format("%Y-%m-%d %H:%M:%S"), profile, message);
+}